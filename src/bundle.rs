@@ -0,0 +1,644 @@
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+use toml::{Table, Value};
+
+use crate::docker::ImageConfig;
+use crate::error::{ClaudepodError, Result};
+use crate::migrations;
+use crate::storage::ContainerInfo;
+
+/// Layout version of the bundle and its manifest
+pub const BUNDLE_SCHEMA_VERSION: u32 = 1;
+
+/// First member of every bundle
+pub const MANIFEST: &str = "manifest.toml";
+
+/// The exported container filesystem. Written as numbered chunks
+/// (`rootfs.tar.000000`, ...), since its size isn't known up front.
+pub const ROOTFS: &str = "rootfs.tar";
+
+/// Last member, with the checksums of the chunked members
+const TRAILER: &str = "trailer.toml";
+
+/// Size of a filesystem chunk (each is buffered in memory while writing)
+const CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Config member appended to plain exports by older versions
+const LEGACY_CONFIG: &str = ".claudepod-config.toml";
+
+/// Describes a bundle and checksums its other members
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub schema_version: u32,
+
+    /// Version of claudepod that wrote the bundle
+    pub claudepod_version: String,
+
+    pub created_at: DateTime<Utc>,
+
+    /// Frozen config of the saved container (including the full profile)
+    pub container: ContainerInfo,
+
+    /// Image settings (user, env, entrypoint) that the filesystem export drops
+    #[serde(default)]
+    pub image_config: Option<ImageConfig>,
+
+    /// sha256 of each member, by member name (the filesystem's is in the trailer)
+    #[serde(default)]
+    pub members: BTreeMap<String, String>,
+}
+
+/// Checksums of members written after the manifest was, by member name
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Trailer {
+    members: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    pub fn new(container: ContainerInfo, image_config: Option<ImageConfig>) -> Self {
+        Self {
+            schema_version: BUNDLE_SCHEMA_VERSION,
+            claudepod_version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: Utc::now(),
+            container,
+            image_config,
+            members: BTreeMap::new(),
+        }
+    }
+
+    /// Parse a manifest, migrating the container config if it is older
+    fn parse(content: &str) -> Result<Self> {
+        let mut table: Table = toml::from_str(content)?;
+
+        let version = table
+            .get("schema_version")
+            .and_then(Value::as_integer)
+            .unwrap_or(0);
+        if version > i64::from(BUNDLE_SCHEMA_VERSION) {
+            return Err(ClaudepodError::Other(format!(
+                "Bundle schema version {} is newer than this claudepod supports ({}). Please upgrade claudepod.",
+                version, BUNDLE_SCHEMA_VERSION
+            )));
+        }
+
+        if let Some(Value::Table(container)) = table.get_mut("container") {
+            migrations::migrate(container, migrations::CONTAINER_MIGRATIONS)?;
+        }
+        Ok(Value::Table(table).try_into()?)
+    }
+}
+
+/// Compression of a bundle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Compression implied by a file name (`.tar.gz`/`.tgz`, `.tar.zst`)
+    pub fn from_path(path: &Path) -> Self {
+        let name = path.to_string_lossy();
+        if name.ends_with(".gz") || name.ends_with(".tgz") {
+            Compression::Gzip
+        } else if name.ends_with(".zst") {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Name on the command line (`--compress`)
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// File extension for a bundle with this compression
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::None => "tar",
+            Compression::Gzip => "tar.gz",
+            Compression::Zstd => "tar.zst",
+        }
+    }
+}
+
+/// Compressing writer that can be finished explicitly (so errors surface)
+enum Encoder<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    fn new(out: W, compression: Compression) -> Result<Self> {
+        Ok(match compression {
+            Compression::None => Encoder::Plain(out),
+            Compression::Gzip => Encoder::Gzip(GzEncoder::new(out, flate2::Compression::default())),
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(out, 0)?),
+        })
+    }
+
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Plain(out) => Ok(out),
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Plain(out) => out.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(out) => out.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// sha256 of some bytes, as lowercase hex
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Member names are plain file names
+fn check_member_name(name: &str) -> Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(ClaudepodError::Other(format!(
+            "Invalid bundle member name: '{}'",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Name of the filesystem chunk at `index`
+fn chunk_name(index: usize) -> String {
+    format!("{}.{:06}", ROOTFS, index)
+}
+
+fn is_chunk(name: &str) -> bool {
+    name.strip_prefix(ROOTFS)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .is_some_and(|index| !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn append<W: Write>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    data: &[u8],
+    mtime: u64,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+/// Fill `buf` from `reader` as far as it goes, returning the length read
+fn read_chunk<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Write a bundle: the manifest (with the checksum of every member added),
+/// the members in order, then the filesystem streamed from `rootfs` and a
+/// trailer with its checksum. A failing `rootfs` read leaves the bundle
+/// without a trailer, so it can't be mistaken for a complete one.
+pub fn write<W: Write, R: Read>(
+    out: W,
+    compression: Compression,
+    mut manifest: Manifest,
+    members: &[(&str, &[u8])],
+    mut rootfs: R,
+) -> Result<W> {
+    for (name, data) in members {
+        check_member_name(name)?;
+        manifest.members.insert(name.to_string(), sha256(data));
+    }
+
+    let mut builder = tar::Builder::new(Encoder::new(out, compression)?);
+    let mtime = manifest.created_at.timestamp().max(0) as u64;
+
+    let content = toml::to_string_pretty(&manifest)?;
+    append(&mut builder, MANIFEST, content.as_bytes(), mtime)?;
+    for (name, data) in members {
+        append(&mut builder, name, data, mtime)?;
+    }
+
+    // At least one chunk, so an empty filesystem is still present
+    let mut hasher = Sha256::new();
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    for index in 0.. {
+        let n = read_chunk(&mut rootfs, &mut chunk)?;
+        if n == 0 && index > 0 {
+            break;
+        }
+        hasher.update(&chunk[..n]);
+        append(&mut builder, &chunk_name(index), &chunk[..n], mtime)?;
+        if n < CHUNK_SIZE {
+            break;
+        }
+    }
+
+    let mut trailer = Trailer::default();
+    trailer
+        .members
+        .insert(ROOTFS.to_string(), format!("{:x}", hasher.finalize()));
+    let content = toml::to_string_pretty(&trailer)?;
+    append(&mut builder, TRAILER, content.as_bytes(), mtime)?;
+
+    Ok(builder.into_inner()?.finish()?)
+}
+
+/// Open a bundle or plain tar stream, detecting its compression
+fn open<'a, R: Read + 'a>(reader: R) -> Result<tar::Archive<Box<dyn Read + 'a>>> {
+    let mut reader = BufReader::new(reader);
+    let mut magic = [0u8; 4];
+    let n = reader.read(&mut magic)?;
+    let reader: Box<dyn Read + 'a> = {
+        let file = io::Cursor::new(magic[..n].to_vec()).chain(reader);
+        match magic {
+            [0x28, 0xb5, 0x2f, 0xfd] => Box::new(zstd::Decoder::new(file)?),
+            [0x1f, 0x8b, ..] => Box::new(GzDecoder::new(file)),
+            _ => Box::new(file),
+        }
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+fn entry_name<R: Read>(entry: &tar::Entry<R>) -> Result<String> {
+    let name = entry.path()?.to_string_lossy().into_owned();
+    check_member_name(&name)?;
+    Ok(name)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The filesystem of a bundle as one stream, read across its chunks. Its
+/// checksum is verified before the end of the stream is reported, so a
+/// consumer never sees a clean end of corrupted or truncated data.
+struct RootfsReader<'a, R: Read> {
+    entries: tar::Entries<'a, R>,
+    current: Option<tar::Entry<'a, R>>,
+    hasher: Sha256,
+    verified: bool,
+}
+
+impl<R: Read> RootfsReader<'_, R> {
+    /// Compare the checksum of everything read with the trailer's
+    fn verify(&mut self, expected: Option<&String>) -> io::Result<()> {
+        let actual = format!("{:x}", std::mem::take(&mut self.hasher).finalize());
+        match expected {
+            Some(expected) if *expected == actual => {
+                self.verified = true;
+                Ok(())
+            }
+            Some(_) => Err(invalid_data(format!(
+                "Checksum mismatch for bundle member '{}'; the file is corrupted",
+                ROOTFS
+            ))),
+            None => Err(invalid_data(format!(
+                "No checksum for bundle member '{}'",
+                ROOTFS
+            ))),
+        }
+    }
+
+    /// Move on from a finished chunk: to the next one, or to the trailer
+    fn next_chunk(&mut self) -> io::Result<()> {
+        self.current = None;
+        let mut entry = self.entries.next().ok_or_else(|| {
+            invalid_data("Bundle trailer is missing; the file is truncated".to_string())
+        })??;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if is_chunk(&name) {
+            self.current = Some(entry);
+            return Ok(());
+        }
+        if name != TRAILER {
+            return Err(invalid_data(format!(
+                "Unexpected bundle member '{}' in the filesystem",
+                name
+            )));
+        }
+
+        let mut content = String::new();
+        entry.read_to_string(&mut content)?;
+        let trailer: Trailer = toml::from_str(&content)
+            .map_err(|e| invalid_data(format!("Invalid bundle trailer: {}", e)))?;
+        self.verify(trailer.members.get(ROOTFS))
+    }
+}
+
+impl<R: Read> Read for RootfsReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(entry) = self.current.as_mut() {
+            let n = entry.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                self.hasher.update(&buf[..n]);
+                return Ok(n);
+            }
+            self.next_chunk()?;
+        }
+        Ok(0)
+    }
+}
+
+/// Read a bundle front to back, verifying checksums. The other members are
+/// checked before `load` is called with the manifest and the filesystem,
+/// which it can stream straight into its consumer; reading the filesystem
+/// to the end fails if it doesn't match its checksum. Returns `None` for a
+/// plain tar without a manifest (older `save` output).
+pub fn read<R: Read, T>(
+    reader: R,
+    load: impl FnOnce(Manifest, &mut dyn Read) -> Result<T>,
+) -> Result<Option<T>> {
+    let mut archive = open(reader)?;
+    let mut entries = archive.entries()?;
+
+    let manifest = match entries.next() {
+        Some(entry) => {
+            let mut entry = entry?;
+            if entry.path()?.to_string_lossy() != MANIFEST {
+                return Ok(None);
+            }
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            Manifest::parse(&content)?
+        }
+        None => return Ok(None),
+    };
+
+    // Members before the filesystem are small and checked right away
+    let mut seen = Vec::new();
+    let first = loop {
+        let mut entry = match entries.next() {
+            Some(entry) => entry?,
+            None => {
+                return Err(ClaudepodError::Other(format!(
+                    "Bundle member '{}' is missing; the file is truncated",
+                    ROOTFS
+                )))
+            }
+        };
+        let name = entry_name(&entry)?;
+        if is_chunk(&name) {
+            break entry;
+        }
+
+        let expected = manifest.members.get(&name).ok_or_else(|| {
+            ClaudepodError::Other(format!("Bundle member '{}' is not in the manifest", name))
+        })?;
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        if sha256(&data) != *expected {
+            return Err(ClaudepodError::Other(format!(
+                "Checksum mismatch for bundle member '{}'; the file is corrupted",
+                name
+            )));
+        }
+        seen.push(name);
+    };
+
+    if let Some(missing) = manifest.members.keys().find(|name| !seen.contains(name)) {
+        return Err(ClaudepodError::Other(format!(
+            "Bundle member '{}' is missing; the file is truncated",
+            missing
+        )));
+    }
+
+    let mut rootfs = RootfsReader {
+        entries,
+        current: Some(first),
+        hasher: Sha256::new(),
+        verified: false,
+    };
+
+    let value = load(manifest, &mut rootfs)?;
+
+    // Whatever `load` left unread still has to match the checksum
+    io::copy(&mut rootfs, &mut io::sink())?;
+    if !rootfs.verified {
+        return Err(ClaudepodError::Other(format!(
+            "Bundle member '{}' could not be verified",
+            ROOTFS
+        )));
+    }
+
+    Ok(Some(value))
+}
+
+/// Config appended to a plain export by older versions of `save`, if any
+pub fn legacy_config(path: &Path) -> Result<Option<String>> {
+    let mut archive = open(File::open(path)?)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?;
+        if path.strip_prefix("./").unwrap_or(&path) == Path::new(LEGACY_CONFIG) {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            return Ok(Some(content));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Profile;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn test_manifest() -> Manifest {
+        Manifest::new(
+            ContainerInfo::new(
+                "test-uuid".to_string(),
+                "default",
+                "claudepod:test".to_string(),
+                Profile::default(),
+            )
+            .unwrap(),
+            Some(ImageConfig {
+                user: "code".to_string(),
+                ..Default::default()
+            }),
+        )
+    }
+
+    /// Bundle a Dockerfile and `rootfs` into `bundle`
+    fn write_bundle(bundle: &Path, compression: Compression, rootfs: &[u8]) {
+        let members: [(&str, &[u8]); 1] = [("Dockerfile", b"FROM ubuntu\n")];
+        write(
+            File::create(bundle).unwrap(),
+            compression,
+            test_manifest(),
+            &members,
+            rootfs,
+        )
+        .unwrap();
+    }
+
+    /// Read a bundle, collecting the manifest and the filesystem
+    fn read_bundle<R: Read>(reader: R) -> Result<Option<(Manifest, Vec<u8>)>> {
+        read(reader, |manifest, rootfs| {
+            let mut data = Vec::new();
+            rootfs.read_to_end(&mut data)?;
+            Ok((manifest, data))
+        })
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let rootfs = vec![7u8; 100_000];
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let dir = TempDir::new().unwrap();
+            let bundle = dir
+                .path()
+                .join(format!("bundle.{}", compression.extension()));
+            write_bundle(&bundle, compression, &rootfs);
+            assert_eq!(Compression::from_path(&bundle), compression);
+            assert_eq!(
+                Compression::from_str(compression.name(), false).unwrap(),
+                compression
+            );
+
+            let (manifest, data) = read_bundle(File::open(&bundle).unwrap()).unwrap().unwrap();
+            assert_eq!(manifest.schema_version, BUNDLE_SCHEMA_VERSION);
+            assert_eq!(manifest.container.uuid, "test-uuid");
+            assert_eq!(manifest.image_config.unwrap().user, "code");
+            assert_eq!(manifest.members.len(), 1);
+            assert_eq!(manifest.members["Dockerfile"], sha256(b"FROM ubuntu\n"));
+            assert_eq!(data, rootfs);
+        }
+    }
+
+    #[test]
+    fn test_stream_roundtrip() {
+        // Spans several chunks, the last one partial
+        let rootfs: Vec<u8> = (0..2 * CHUNK_SIZE + 3).map(|i| (i % 251) as u8).collect();
+        let dir = TempDir::new().unwrap();
+        let bundle = dir.path().join("bundle.tar.zst");
+        write_bundle(&bundle, Compression::Zstd, &rootfs);
+
+        // A pipe is read once, front to back
+        let data = fs::read(&bundle).unwrap();
+        let (manifest, read_back) = read_bundle(&data[..]).unwrap().unwrap();
+        assert_eq!(manifest.container.uuid, "test-uuid");
+        assert!(read_back == rootfs);
+
+        // An empty filesystem is still a member
+        write_bundle(&bundle, Compression::None, &[]);
+        let (_, read_back) = read_bundle(File::open(&bundle).unwrap()).unwrap().unwrap();
+        assert!(read_back.is_empty());
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let dir = TempDir::new().unwrap();
+        let bundle = dir.path().join("bundle.tar");
+        write_bundle(&bundle, Compression::None, &[7u8; 100_000]);
+
+        // Flip a byte of the filesystem (its data is all 7s)
+        let mut data = fs::read(&bundle).unwrap();
+        let pos = data.iter().rposition(|&b| b == 7).unwrap();
+        data[pos] = 8;
+
+        let err = read_bundle(&data[..]).unwrap_err();
+        assert!(err.to_string().contains("Checksum mismatch"), "{}", err);
+    }
+
+    #[test]
+    fn test_truncated_and_failed_export() {
+        let dir = TempDir::new().unwrap();
+        let bundle = dir.path().join("bundle.tar");
+        write_bundle(&bundle, Compression::None, &[7u8; 100_000]);
+
+        // Cut off right after the filesystem, before the trailer
+        let data = fs::read(&bundle).unwrap();
+        let end = (data.iter().rposition(|&b| b == 7).unwrap() + 512) / 512 * 512;
+        let err = read_bundle(&data[..end]).unwrap_err();
+        assert!(err.to_string().contains("truncated"), "{}", err);
+
+        // A failing export doesn't produce a bundle
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("export failed"))
+            }
+        }
+        let result = write(Vec::new(), Compression::None, test_manifest(), &[], Failing);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_legacy_export() {
+        let dir = TempDir::new().unwrap();
+        let export = dir.path().join("export.tar");
+        fs::write(dir.path().join("hello"), "hi").unwrap();
+        fs::write(dir.path().join(LEGACY_CONFIG), "profile = \"default\"\n").unwrap();
+
+        let mut builder = tar::Builder::new(File::create(&export).unwrap());
+        builder
+            .append_path_with_name(dir.path().join("hello"), "etc/hello")
+            .unwrap();
+        builder
+            .append_path_with_name(dir.path().join(LEGACY_CONFIG), LEGACY_CONFIG)
+            .unwrap();
+        builder.finish().unwrap();
+
+        assert!(read_bundle(File::open(&export).unwrap()).unwrap().is_none());
+        assert_eq!(
+            legacy_config(&export).unwrap().as_deref(),
+            Some("profile = \"default\"\n")
+        );
+    }
+
+    #[test]
+    fn test_member_names() {
+        assert!(check_member_name("rootfs.tar").is_ok());
+        assert!(is_chunk("rootfs.tar.000012"));
+        assert!(!is_chunk("rootfs.tar"));
+        assert!(!is_chunk("rootfs.tar.x"));
+        for name in ["", "..", "../etc/passwd", "dir/file"] {
+            assert!(check_member_name(name).is_err(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use clap::ValueEnum;
+use clap_complete::{generate, Shell};
+
+/// Shells with completion scripts (static clap completions plus dynamic names)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// What `claudepod __complete` lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CandidateKind {
+    /// Containers of the current project
+    Containers,
+    /// Available profiles
+    Profiles,
+    /// Commands of the selected container's frozen config
+    Commands,
+}
+
+/// Bash: dynamic names for `-c`, `init` and `run`, clap's completion otherwise
+const BASH_DYNAMIC: &str = r#"
+_claudepod_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}" kind=""
+    if [[ "$prev" == "-c" || "$prev" == "--container" ]]; then
+        kind=containers
+    elif [[ $COMP_CWORD -eq 2 && "${COMP_WORDS[1]}" == "init" ]]; then
+        kind=profiles
+    elif [[ $COMP_CWORD -eq 2 && "${COMP_WORDS[1]}" == "run" ]]; then
+        kind=commands
+    fi
+    if [[ -n "$kind" ]]; then
+        COMPREPLY=($(compgen -W "$(claudepod __complete "$kind" 2>/dev/null)" -- "$cur"))
+        return 0
+    fi
+    _claudepod "$@"
+}
+complete -F _claudepod_dynamic -o bashdefault -o default claudepod
+"#;
+
+/// Zsh: same dispatch as bash, falling back to clap's `_claudepod`
+const ZSH_DYNAMIC: &str = r#"
+_claudepod_dynamic() {
+    local kind=""
+    if [[ "${words[CURRENT-1]}" == (-c|--container) ]]; then
+        kind=containers
+    elif (( CURRENT == 3 )) && [[ "${words[2]}" == init ]]; then
+        kind=profiles
+    elif (( CURRENT == 3 )) && [[ "${words[2]}" == run ]]; then
+        kind=commands
+    fi
+    if [[ -n "$kind" ]]; then
+        compadd -- ${(f)"$(claudepod __complete $kind 2>/dev/null)"}
+        return
+    fi
+    _claudepod "$@"
+}
+compdef _claudepod_dynamic claudepod
+"#;
+
+/// Fish: extra candidates next to clap's static ones
+const FISH_DYNAMIC: &str = r#"
+complete -c claudepod -s c -l container -x -a '(claudepod __complete containers 2>/dev/null)'
+complete -c claudepod -n '__fish_seen_subcommand_from init' -f -a '(claudepod __complete profiles 2>/dev/null)'
+complete -c claudepod -n '__fish_seen_subcommand_from run' -f -a '(claudepod __complete commands 2>/dev/null)'
+"#;
+
+/// Generate the completion script for `shell`
+pub fn script(shell: CompletionShell, cmd: &mut clap::Command) -> String {
+    let (clap_shell, dynamic) = match shell {
+        CompletionShell::Bash => (Shell::Bash, BASH_DYNAMIC),
+        CompletionShell::Zsh => (Shell::Zsh, ZSH_DYNAMIC),
+        CompletionShell::Fish => (Shell::Fish, FISH_DYNAMIC),
+    };
+
+    let mut buf = Vec::new();
+    generate(clap_shell, cmd, "claudepod", &mut buf);
+
+    let mut script = String::from_utf8_lossy(&buf).into_owned();
+    script.push_str(dynamic);
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, Command};
+
+    fn test_command() -> Command {
+        Command::new("claudepod")
+            .arg(Arg::new("container").short('c').long("container"))
+            .subcommand(Command::new("init").arg(Arg::new("profile")))
+            .subcommand(Command::new("run").arg(Arg::new("command")))
+    }
+
+    #[test]
+    fn test_scripts() {
+        for shell in [
+            CompletionShell::Bash,
+            CompletionShell::Zsh,
+            CompletionShell::Fish,
+        ] {
+            let script = script(shell, &mut test_command());
+            assert!(script.contains("claudepod __complete"), "{:?}", shell);
+            assert!(script.contains("init"), "{:?}", shell);
+        }
+
+        // The dynamic wrappers fall back to clap's generated function
+        let bash = script(CompletionShell::Bash, &mut test_command());
+        assert!(bash.contains("_claudepod()"));
+        assert!(
+            bash.ends_with("complete -F _claudepod_dynamic -o bashdefault -o default claudepod\n")
+        );
+
+        let zsh = script(CompletionShell::Zsh, &mut test_command());
+        assert!(zsh.contains("_claudepod()"));
+    }
+}
@@ -1,8 +1,254 @@
-use std::path::Path;
-use std::process::{Command, Stdio};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdout, Command, Stdio};
 
 use crate::error::{ClaudepodError, Result};
-use crate::profile::{CommandsConfig, DockerConfig};
+use crate::generator::{ENTRYPOINT_LABEL, ENTRYPOINT_PATH, ENTRYPOINT_VERSION};
+use crate::gpu;
+use crate::network::{self, EgressProxy, EGRESS_NETWORK};
+use crate::paths;
+use crate::profile::{
+    ContainerConfig, DeviceMount, DockerConfig, GpuConfig, HookConfig, HooksConfig, NetworkConfig,
+    PortMapping, Profile, ResourcesConfig, SecurityConfig,
+};
+
+/// What to execute in a running container, and how
+#[derive(Debug, Clone)]
+pub struct ExecOptions {
+    /// Executable followed by its arguments
+    pub argv: Vec<String>,
+
+    /// Exec-time environment variables
+    pub env: Vec<(String, String)>,
+
+    /// Working directory inside the container
+    pub working_dir: PathBuf,
+
+    /// User to run as (default: the image's user)
+    pub user: Option<String>,
+
+    /// Shell snippet to run before the command (aborts on failure)
+    pub pre: Option<String>,
+
+    /// Shell snippet to run after the command, whatever its exit code
+    pub post: Option<String>,
+}
+
+impl ExecOptions {
+    /// The command line to exec: `argv`, wrapped in a bash script when there are
+    /// pre/post snippets. The command's exit code is preserved.
+    pub fn command_line(&self) -> Vec<String> {
+        if self.pre.is_none() && self.post.is_none() {
+            return self.argv.clone();
+        }
+
+        let mut script = String::new();
+        if let Some(ref pre) = self.pre {
+            script.push_str(&format!("set -e\n{}\nset +e\n", pre));
+        }
+        script.push_str("\"$@\"\nstatus=$?\n");
+        if let Some(ref post) = self.post {
+            script.push_str(&format!("{}\n", post));
+        }
+        script.push_str("exit $status");
+
+        let mut command = vec![
+            "bash".to_string(),
+            "-c".to_string(),
+            script,
+            "claudepod".to_string(),
+        ];
+        command.extend(self.argv.iter().cloned());
+        command
+    }
+}
+
+/// Image settings that `export` drops, restored with `import --change`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImageConfig {
+    #[serde(default)]
+    pub user: String,
+
+    /// KEY=VALUE entries
+    #[serde(default)]
+    pub env: Vec<String>,
+
+    #[serde(default)]
+    pub entrypoint: Vec<String>,
+
+    #[serde(default)]
+    pub cmd: Vec<String>,
+
+    #[serde(default)]
+    pub working_dir: String,
+
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Quote a value for a Dockerfile instruction, so spaces and `$` stay literal
+fn dockerfile_quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        if matches!(c, '\\' | '"' | '$') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl ImageConfig {
+    /// Parse the `.Config` object printed by `image inspect`
+    pub fn from_inspect_json(json: &str) -> Result<Self> {
+        let config: serde_json::Value = serde_json::from_str(json)?;
+
+        let string = |key: &str| config[key].as_str().unwrap_or_default().to_string();
+        // Arrays may be null, and some podman versions print a plain string
+        let list = |key: &str| match &config[key] {
+            serde_json::Value::Array(items) => items
+                .iter()
+                .filter_map(|item| item.as_str().map(str::to_string))
+                .collect(),
+            serde_json::Value::String(item) => vec![item.clone()],
+            _ => Vec::new(),
+        };
+
+        Ok(Self {
+            user: string("User"),
+            env: list("Env"),
+            entrypoint: list("Entrypoint"),
+            cmd: list("Cmd"),
+            working_dir: string("WorkingDir"),
+            labels: match &config["Labels"] {
+                serde_json::Value::Object(labels) => labels
+                    .iter()
+                    .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+                    .collect(),
+                _ => BTreeMap::new(),
+            },
+        })
+    }
+
+    /// What the generated Dockerfile sets, for exports saved without the config
+    pub fn from_profile(profile: &Profile) -> Self {
+        let home = &profile.container.home_dir;
+        Self {
+            user: profile.container.user.clone(),
+            env: vec![
+                format!("HOME={}", home),
+                format!(
+                    "PATH={}/.npm-global/bin:/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin",
+                    home
+                ),
+            ],
+            entrypoint: vec![ENTRYPOINT_PATH.to_string()],
+            cmd: vec!["bash".to_string()],
+            working_dir: String::new(),
+            labels: BTreeMap::new(),
+        }
+    }
+
+    /// Dockerfile instructions for `import --change`
+    pub fn changes(&self) -> Vec<String> {
+        let mut changes = Vec::new();
+        if !self.user.is_empty() {
+            changes.push(format!("USER {}", self.user));
+        }
+        for entry in &self.env {
+            if let Some((key, value)) = entry.split_once('=') {
+                changes.push(format!("ENV {}={}", key, dockerfile_quote(value)));
+            }
+        }
+        if !self.working_dir.is_empty() {
+            changes.push(format!("WORKDIR {}", self.working_dir));
+        }
+        for (key, value) in &self.labels {
+            changes.push(format!("LABEL {}={}", key, dockerfile_quote(value)));
+        }
+        // JSON (exec) form, so no shell is involved
+        if !self.entrypoint.is_empty() {
+            changes.push(format!(
+                "ENTRYPOINT {}",
+                serde_json::to_string(&self.entrypoint).unwrap_or_default()
+            ));
+        }
+        if !self.cmd.is_empty() {
+            changes.push(format!(
+                "CMD {}",
+                serde_json::to_string(&self.cmd).unwrap_or_default()
+            ));
+        }
+        changes
+    }
+}
+
+/// The (frozen) profile sections a container is created from
+pub struct ContainerSpec<'a> {
+    pub container: &'a ContainerConfig,
+    pub docker: &'a DockerConfig,
+    pub network: &'a NetworkConfig,
+    pub resources: &'a ResourcesConfig,
+    pub security: &'a SecurityConfig,
+    pub gpu: &'a GpuConfig,
+    pub hooks: &'a HooksConfig,
+    /// Overlay workspace copy mounted at the project path instead of the
+    /// project itself
+    pub workspace: Option<&'a Path>,
+}
+
+impl<'a> ContainerSpec<'a> {
+    /// Take all sections straight from a profile
+    pub fn from_profile(profile: &'a Profile) -> Self {
+        Self {
+            container: &profile.container,
+            docker: &profile.docker,
+            network: &profile.network,
+            resources: &profile.resources,
+            security: &profile.security,
+            gpu: &profile.gpu,
+            hooks: &profile.hooks,
+            workspace: None,
+        }
+    }
+}
+
+/// A running container export, read as a tar stream. Reading to the end
+/// fails if the export itself failed, so a cut-off stream is never taken
+/// for a complete one.
+pub struct ExportStream {
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl Read for ExportStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!(
+                    "container export failed ({})",
+                    status
+                )));
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl Drop for ExportStream {
+    fn drop(&mut self) {
+        // Stop an export that was abandoned halfway
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
 
 pub struct DockerClient;
 
@@ -82,16 +328,17 @@ impl DockerClient {
 
     /// Run a command in a container for a project
     pub fn run(
-        docker: &DockerConfig,
-        commands: &CommandsConfig,
+        spec: &ContainerSpec,
         container_name: &str,
         image_tag: &str,
-        command_name: &str,
-        args: &[String],
+        exec: &ExecOptions,
         project_dir: &Path,
-        working_dir: &Path,
     ) -> Result<()> {
-        let runtime = &docker.container_runtime;
+        let runtime = &spec.docker.container_runtime;
+
+        // The proxy lives for this session, so hooks get through it as well
+        let proxy = Self::start_egress_proxy(spec.network, runtime, container_name)?;
+        let proxy_env = proxy.as_ref().map(EgressProxy::env).unwrap_or_default();
 
         // Check if container exists
         let container_exists = Self::container_exists(container_name, runtime);
@@ -99,37 +346,231 @@ impl DockerClient {
         if container_exists {
             // Start container if needed
             if !Self::container_is_running(container_name, runtime) {
-                println!("Starting container...");
-                Self::start_container(container_name, runtime)?;
+                Self::start_with_hooks(spec, container_name, project_dir, false, &proxy_env)?;
             }
         } else {
             // Create new container
             println!("Creating container: {}", container_name);
-            Self::create_container(docker, image_tag, project_dir, container_name)?;
-            println!("Starting container...");
-            Self::start_container(container_name, runtime)?;
+            Self::create_container(spec, image_tag, project_dir, container_name)?;
+            Self::start_created(spec, container_name, project_dir, &proxy_env)?;
         }
 
+        let mut exec = exec.clone();
+        exec.env.extend(proxy_env.iter().cloned());
+
         // Execute command in the running container
-        Self::exec_in_container(
-            docker,
-            commands,
+        let code = Self::exec_in_container(spec.docker, container_name, &exec)?;
+
+        // A failing exit hook shouldn't mask the command's own result
+        if let Err(e) = Self::run_hooks(
+            &spec.hooks.on_run_exit,
+            runtime,
             container_name,
-            command_name,
-            args,
             project_dir,
-            working_dir,
+            Some(code),
+            &proxy_env,
+        ) {
+            eprintln!("Warning: {}", e);
+        }
+
+        if code != 0 {
+            return Err(ClaudepodError::Docker(format!(
+                "Command exited with code: {}",
+                code
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// In allowlist mode the container sits on an internal network and can
+    /// only get out through an egress proxy, which stops when dropped
+    pub fn start_egress_proxy(
+        network: &NetworkConfig,
+        runtime: &str,
+        container_name: &str,
+    ) -> Result<Option<EgressProxy>> {
+        if network.mode != "allowlist" {
+            return Ok(None);
+        }
+
+        let gateway = network::ensure_egress_network(runtime)?;
+        let proxy = EgressProxy::start(
+            &format!("{}:0", gateway),
+            network.allow.clone(),
+            paths::logs_dir().join("egress.log"),
+            container_name,
+        )?;
+        println!("Egress allowlist active (proxy on {})", proxy.addr());
+        Ok(Some(proxy))
+    }
+
+    /// Start a container, then run its on_create hooks (if it was just created)
+    /// and its on_start hooks. `env` is passed to hooks run in the container.
+    fn start_with_hooks(
+        spec: &ContainerSpec,
+        container_name: &str,
+        project_dir: &Path,
+        created: bool,
+        env: &[(String, String)],
+    ) -> Result<()> {
+        let runtime = &spec.docker.container_runtime;
+
+        println!("Starting container...");
+        Self::start_container(container_name, runtime)?;
+
+        if created {
+            Self::run_hooks(
+                &spec.hooks.on_create,
+                runtime,
+                container_name,
+                project_dir,
+                None,
+                env,
+            )?;
+        }
+        Self::run_hooks(
+            &spec.hooks.on_start,
+            runtime,
+            container_name,
+            project_dir,
+            None,
+            env,
         )
     }
 
+    /// First start of a container that was just created, with its on_create
+    /// hooks. If that fails the container is removed, so it is created (and
+    /// set up) again next time rather than found half set up.
+    pub fn start_created(
+        spec: &ContainerSpec,
+        container_name: &str,
+        project_dir: &Path,
+        env: &[(String, String)],
+    ) -> Result<()> {
+        Self::start_with_hooks(spec, container_name, project_dir, true, env).inspect_err(|_| {
+            let _ = Self::remove_container(container_name, &spec.docker.container_runtime);
+        })
+    }
+
+    /// Variables exposed to hooks
+    fn hook_vars(
+        container_name: &str,
+        project_dir: &Path,
+        exit_code: Option<i32>,
+    ) -> Vec<(String, String)> {
+        let mut vars = vec![
+            (
+                "CLAUDEPOD_PROJECT_DIR".to_string(),
+                project_dir.to_string_lossy().to_string(),
+            ),
+            (
+                "CLAUDEPOD_CONTAINER".to_string(),
+                container_name.to_string(),
+            ),
+        ];
+        if let Some(code) = exit_code {
+            vars.push(("CLAUDEPOD_EXIT_CODE".to_string(), code.to_string()));
+        }
+        vars
+    }
+
+    /// Build the command for a hook: `bash -c` on the host (in the project
+    /// directory), or an exec inside the container, which also gets `env`
+    /// and goes through the entrypoint if `wrap` is set
+    fn hook_command(
+        hook: &HookConfig,
+        runtime: &str,
+        container_name: &str,
+        project_dir: &Path,
+        exit_code: Option<i32>,
+        env: &[(String, String)],
+        wrap: bool,
+    ) -> Command {
+        let vars = Self::hook_vars(container_name, project_dir, exit_code);
+
+        let mut cmd = if hook.host {
+            let mut cmd = Command::new("bash");
+            cmd.current_dir(project_dir).envs(vars);
+            cmd
+        } else {
+            let mut cmd = Command::new(runtime);
+            cmd.arg("exec")
+                .arg("-w")
+                .arg(project_dir.to_string_lossy().as_ref());
+            for (key, value) in vars.iter().chain(env) {
+                cmd.arg("-e").arg(format!("{}={}", key, value));
+            }
+            cmd.arg(container_name);
+            if wrap {
+                cmd.arg(ENTRYPOINT_PATH);
+            }
+            cmd.arg("bash");
+            cmd
+        };
+        cmd.arg("-c").arg(&hook.command);
+        cmd
+    }
+
+    /// Run hooks in order, stopping at the first failure
+    fn run_hooks(
+        hooks: &[HookConfig],
+        runtime: &str,
+        container_name: &str,
+        project_dir: &Path,
+        exit_code: Option<i32>,
+        env: &[(String, String)],
+    ) -> Result<()> {
+        let wrap = hooks.iter().any(|hook| !hook.host)
+            && Self::entrypoint_wraps_exec(container_name, runtime);
+
+        for hook in hooks {
+            let place = if hook.host { "host" } else { "container" };
+            println!("Running hook ({}): {}", place, hook.command);
+
+            let status = Self::hook_command(
+                hook,
+                runtime,
+                container_name,
+                project_dir,
+                exit_code,
+                env,
+                wrap,
+            )
+            .stdin(Stdio::null())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .map_err(|e| ClaudepodError::Docker(format!("Failed to run hook: {}", e)))?;
+
+            if !status.success() {
+                return Err(ClaudepodError::Docker(format!(
+                    "Hook '{}' exited with code: {}",
+                    hook.command,
+                    status.code().unwrap_or(-1)
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Create a persistent container
     pub fn create_container(
-        docker: &DockerConfig,
+        spec: &ContainerSpec,
         image_tag: &str,
         project_dir: &Path,
         container_name: &str,
     ) -> Result<()> {
+        let docker = spec.docker;
+        let network = spec.network;
         let runtime = &docker.container_runtime;
+
+        // Host paths are only checked here, where they have to exist
+        for device in &docker.devices {
+            device.check_host()?;
+        }
+        spec.security.check_host()?;
+
         let mut cmd = Command::new(runtime);
         cmd.args(["create", "--name", container_name]);
 
@@ -147,16 +588,29 @@ impl DockerClient {
         cmd.arg("-e").arg(format!("UID={}", Self::get_uid()));
         cmd.arg("-e").arg(format!("GID={}", Self::get_gid()));
 
-        // Always mount the project directory to the same path in container
+        // Always mount the project directory (or its overlay copy) to the
+        // same path in container
         let project_dir_str = project_dir.to_string_lossy();
+        let source = spec.workspace.unwrap_or(project_dir);
         cmd.arg("-v")
-            .arg(format!("{}:{}", project_dir_str, project_dir_str));
+            .arg(format!("{}:{}", source.to_string_lossy(), project_dir_str));
 
         // Mount additional volumes from config
         for volume in &docker.volumes {
             let host_path = shellexpand::full(&volume.host)
                 .map_err(|e| ClaudepodError::Docker(format!("Failed to expand path: {}", e)))?;
 
+            // Mounting the project itself would bypass the overlay copy
+            if spec.workspace.is_some() && Path::new(host_path.as_ref()).starts_with(project_dir) {
+                if Path::new(host_path.as_ref()) != project_dir {
+                    eprintln!(
+                        "Warning: not mounting {} in overlay workspace mode (it is inside the project)",
+                        host_path
+                    );
+                }
+                continue;
+            }
+
             let container_path = shellexpand::full(&volume.container)
                 .map_err(|e| ClaudepodError::Docker(format!("Failed to expand path: {}", e)))?;
 
@@ -176,26 +630,60 @@ impl DockerClient {
             cmd.arg("--tmpfs").arg(tmpfs_arg);
         }
 
-        // GPU support
-        if docker.enable_gpu {
-            if docker.container_runtime == "podman" {
-                // Podman uses CDI (Container Device Interface) for GPU access
-                let cdi_device = if docker.gpu_driver == "all" {
-                    "nvidia.com/gpu=all".to_string()
-                } else {
-                    format!("nvidia.com/gpu={}", docker.gpu_driver)
-                };
-                cmd.arg("--device").arg(cdi_device);
-            } else {
-                cmd.arg("--gpus").arg(&docker.gpu_driver);
+        // Published ports
+        cmd.args(Self::port_args(&docker.ports));
+
+        // Device passthrough
+        cmd.args(Self::device_args(&docker.devices));
+
+        // GPU support (vendor devices, CDI specs or --gpus, depending on runtime)
+        let gpu = gpu::gpu_args(docker, spec.gpu, Path::new("/"));
+        for warning in &gpu.warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        cmd.args(gpu.args);
+
+        // Host driver libraries (e.g. OptiX) mounted read-only at their host paths
+        if spec.gpu.host_drivers == "mount" {
+            let files = spec.gpu.resolve_host_drivers()?;
+            if files.is_empty() {
+                eprintln!("Warning: gpu.host_drivers = \"mount\" but no host driver files matched");
             }
+            cmd.args(Self::host_driver_mount_args(&files));
         }
 
+        // Network mode
+        match network.mode.as_str() {
+            "none" | "host" => {
+                cmd.arg("--network").arg(&network.mode);
+            }
+            "allowlist" => {
+                network::ensure_egress_network(runtime)?;
+                cmd.arg("--network").arg(EGRESS_NETWORK);
+            }
+            _ => {}
+        }
+
+        // Resource limits
+        cmd.args(Self::resource_args(spec.resources));
+
+        // Security hardening
+        cmd.args(Self::security_args(
+            spec.security,
+            spec.container,
+            docker,
+            runtime,
+        )?);
+
         // Extra Docker arguments
         for arg in &docker.extra_args {
             cmd.arg(arg);
         }
 
+        // Run the generated entrypoint as the main process so its one-time init
+        // happens at container start, even for images whose metadata lost it
+        cmd.arg("--entrypoint").arg(ENTRYPOINT_PATH);
+
         // Image tag
         cmd.arg(image_tag);
 
@@ -217,44 +705,187 @@ impl DockerClient {
         Ok(())
     }
 
-    /// Execute a command in a running container
+    /// Read-only bind mounts for host driver files, at the same path in the container
+    fn host_driver_mount_args(files: &[std::path::PathBuf]) -> Vec<String> {
+        files
+            .iter()
+            .map(|f| format!("--volume={}:{}:ro", f.display(), f.display()))
+            .collect()
+    }
+
+    /// Translate port mappings into `--publish` flags (same syntax for docker and podman)
+    fn port_args(ports: &[PortMapping]) -> Vec<String> {
+        ports
+            .iter()
+            .map(|p| {
+                let address = if p.address.contains(':') {
+                    format!("[{}]", p.address)
+                } else {
+                    p.address.clone()
+                };
+                let host = if p.host == 0 {
+                    String::new()
+                } else {
+                    p.host.to_string()
+                };
+                format!(
+                    "--publish={}:{}:{}/{}",
+                    address, host, p.container, p.protocol
+                )
+            })
+            .collect()
+    }
+
+    /// Translate device mappings into `--device` flags (same syntax for docker and podman)
+    fn device_args(devices: &[DeviceMount]) -> Vec<String> {
+        devices
+            .iter()
+            .map(|d| {
+                format!(
+                    "--device={}:{}:{}",
+                    d.host,
+                    d.container_path(),
+                    d.permissions
+                )
+            })
+            .collect()
+    }
+
+    /// Translate resource limits into `create` flags (shared by docker and podman).
+    /// "unlimited" ulimits are written as -1, which both runtimes accept.
+    fn resource_args(resources: &ResourcesConfig) -> Vec<String> {
+        let mut args = Vec::new();
+
+        if let Some(cpus) = resources.cpus {
+            args.push(format!("--cpus={}", cpus));
+        }
+        if let Some(ref memory) = resources.memory {
+            args.push(format!("--memory={}", memory.to_ascii_lowercase()));
+        }
+        if let Some(ref swap) = resources.memory_swap {
+            args.push(format!("--memory-swap={}", swap.to_ascii_lowercase()));
+        }
+        if let Some(pids) = resources.pids_limit {
+            args.push(format!("--pids-limit={}", pids));
+        }
+        if let Some(ref shm) = resources.shm_size {
+            args.push(format!("--shm-size={}", shm.to_ascii_lowercase()));
+        }
+        for (name, value) in &resources.ulimits {
+            let value = value
+                .split(':')
+                .map(|v| if v == "unlimited" { "-1" } else { v })
+                .collect::<Vec<_>>()
+                .join(":");
+            args.push(format!("--ulimit={}={}", name, value));
+        }
+
+        args
+    }
+
+    /// Translate security options into `create` flags. With a read-only root,
+    /// /tmp gets a tmpfs (podman's --read-only already covers /tmp, /var/tmp and
+    /// /run) and the home directory an anonymous volume, seeded from the image,
+    /// so the entrypoint's init can still write there at container start.
+    fn security_args(
+        security: &SecurityConfig,
+        container: &ContainerConfig,
+        docker: &DockerConfig,
+        runtime: &str,
+    ) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+
+        for cap in &security.cap_drop {
+            args.push(format!("--cap-drop={}", cap));
+        }
+        for cap in &security.cap_add {
+            args.push(format!("--cap-add={}", cap));
+        }
+
+        if security.no_new_privileges {
+            args.push("--security-opt=no-new-privileges".to_string());
+        }
+
+        if let Some(ref seccomp) = security.seccomp {
+            let path = shellexpand::full(seccomp)
+                .map_err(|e| ClaudepodError::Docker(format!("Failed to expand path: {}", e)))?;
+            args.push(format!("--security-opt=seccomp={}", path));
+        }
+
+        if security.read_only_root {
+            args.push("--read-only".to_string());
+
+            if runtime != "podman" {
+                for path in ["/tmp", "/var/tmp"] {
+                    if !docker
+                        .tmpfs
+                        .iter()
+                        .any(|t| t.path.trim_end_matches('/') == path)
+                    {
+                        args.push(format!("--tmpfs={}:rw,mode=1777", path));
+                    }
+                }
+            }
+
+            let home = container.home_dir.trim_end_matches('/');
+            let home_mounted = docker
+                .tmpfs
+                .iter()
+                .any(|t| t.path.trim_end_matches('/') == home)
+                || docker
+                    .volumes
+                    .iter()
+                    .any(|v| v.container.trim_end_matches('/') == home);
+            if !home_mounted {
+                args.push(format!("--volume={}", home));
+            }
+        }
+
+        Ok(args)
+    }
+
+    /// Execute a command in a running container, returning its exit code
     fn exec_in_container(
         docker: &DockerConfig,
-        commands: &CommandsConfig,
         container_name: &str,
-        command_name: &str,
-        args: &[String],
-        _project_dir: &Path,
-        working_dir: &Path,
-    ) -> Result<()> {
-        // Resolve the command
-        let (executable, cmd_config) = commands.resolve(command_name)?;
-
+        exec: &ExecOptions,
+    ) -> Result<i32> {
         let runtime = &docker.container_runtime;
         let mut cmd = Command::new(runtime);
         cmd.args(["exec", "-it"]);
 
         // Set working directory
-        let work_dir = working_dir.to_string_lossy();
+        let work_dir = exec.working_dir.to_string_lossy();
         cmd.arg("-w").arg(work_dir.as_ref());
 
-        cmd.arg(container_name);
-
-        // Add the executable
-        cmd.arg(&executable);
+        if let Some(ref user) = exec.user {
+            cmd.arg("--user").arg(user);
+        }
 
-        // Add configured args (parse them as space-separated)
-        if !cmd_config.args.is_empty() {
-            for arg in cmd_config.args.split_whitespace() {
-                cmd.arg(arg);
-            }
+        // Exec-time environment: values are handed over through the runtime's
+        // own environment so they never appear on its command line
+        for (key, value) in &exec.env {
+            cmd.env(key, value);
+            cmd.arg("-e").arg(key);
         }
 
-        // Add user-provided arguments
-        for arg in args {
-            cmd.arg(arg);
+        cmd.arg(container_name);
+
+        // Route through the entrypoint so ~/.env (PATH, environment, aliases)
+        // is sourced exactly as in an interactive shell. The entrypoint of
+        // older images can't do that without corrupting stdout.
+        if Self::entrypoint_wraps_exec(container_name, runtime) {
+            cmd.arg(ENTRYPOINT_PATH);
+        } else {
+            eprintln!(
+                "Warning: container '{}' was built with an older entrypoint, so commands run without the profile's environment and aliases. Rebuild it with `claudepod init --force`.",
+                container_name
+            );
         }
 
+        // The command line (executable followed by its arguments, plus pre/post)
+        cmd.args(exec.command_line());
+
         // Execute the command, inheriting stdio
         let status = cmd
             .stdin(Stdio::inherit())
@@ -263,14 +894,29 @@ impl DockerClient {
             .status()
             .map_err(|e| ClaudepodError::Docker(format!("Failed to exec in container: {}", e)))?;
 
-        if !status.success() {
-            return Err(ClaudepodError::Docker(format!(
-                "Command exited with code: {}",
-                status.code().unwrap_or(-1)
-            )));
-        }
+        Ok(status.code().unwrap_or(-1))
+    }
 
-        Ok(())
+    /// Whether the container's image has an entrypoint that can wrap execs,
+    /// going by its `ENTRYPOINT_LABEL`
+    fn entrypoint_wraps_exec(container_name: &str, runtime: &str) -> bool {
+        Command::new(runtime)
+            .args([
+                "inspect",
+                "--format",
+                "{{json .Config.Labels}}",
+                container_name,
+            ])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| {
+                serde_json::from_slice::<Option<BTreeMap<String, String>>>(&output.stdout).ok()
+            })
+            .flatten()
+            .is_some_and(|labels| {
+                labels.get(ENTRYPOINT_LABEL).map(String::as_str) == Some(ENTRYPOINT_VERSION)
+            })
     }
 
     /// Get current user UID
@@ -349,7 +995,7 @@ impl DockerClient {
     /// Remove a container
     pub fn remove_container(container_name: &str, runtime: &str) -> Result<()> {
         let output = Command::new(runtime)
-            .args(["rm", "-f", container_name])
+            .args(["rm", "-f", "-v", container_name])
             .output()
             .map_err(|e| ClaudepodError::Docker(format!("Failed to remove container: {}", e)))?;
 
@@ -397,16 +1043,33 @@ impl DockerClient {
         Ok(())
     }
 
-    /// Start a stopped container
-    pub fn start_container(container_name: &str, runtime: &str) -> Result<()> {
+    /// Add a tag to an existing image
+    pub fn tag_image(source: &str, target: &str, runtime: &str) -> Result<()> {
         let output = Command::new(runtime)
-            .args(["start", container_name])
+            .args(["tag", source, target])
             .output()
-            .map_err(|e| ClaudepodError::Docker(format!("Failed to start container: {}", e)))?;
+            .map_err(|e| ClaudepodError::Docker(format!("Failed to tag image: {}", e)))?;
 
         if !output.status.success() {
             return Err(ClaudepodError::Docker(format!(
-                "Failed to start container: {}",
+                "Failed to tag image: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Remove an image tag (the image goes once its last tag is removed)
+    pub fn remove_image(image_tag: &str, runtime: &str) -> Result<()> {
+        let output = Command::new(runtime)
+            .args(["rmi", image_tag])
+            .output()
+            .map_err(|e| ClaudepodError::Docker(format!("Failed to remove image: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(ClaudepodError::Docker(format!(
+                "Failed to remove image: {}",
                 String::from_utf8_lossy(&output.stderr)
             )));
         }
@@ -414,47 +1077,179 @@ impl DockerClient {
         Ok(())
     }
 
-    /// Export container filesystem to a tar file
-    pub fn export_container(container_name: &str, output_path: &Path, runtime: &str) -> Result<()> {
+    /// Tags of the images in a repository (e.g. "claudepod")
+    pub fn list_image_tags(repository: &str, runtime: &str) -> Result<Vec<String>> {
         let output = Command::new(runtime)
             .args([
-                "export",
-                container_name,
-                "-o",
-                &output_path.to_string_lossy(),
+                "images",
+                "--filter",
+                &format!("reference={}", repository),
+                "--format",
+                "{{.Tag}}",
             ])
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
             .output()
-            .map_err(|e| ClaudepodError::Docker(format!("Failed to export container: {}", e)))?;
+            .map_err(|e| ClaudepodError::Docker(format!("Failed to list images: {}", e)))?;
 
         if !output.status.success() {
             return Err(ClaudepodError::Docker(format!(
-                "Failed to export container: {}",
+                "Failed to list images: {}",
                 String::from_utf8_lossy(&output.stderr)
             )));
         }
 
-        Ok(())
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty() && *tag != "<none>")
+            .map(str::to_string)
+            .collect())
     }
 
-    /// Import a tar file as a container image
-    pub fn import_image(tarfile: &Path, image_tag: &str, runtime: &str) -> Result<()> {
+    /// Start a stopped container
+    pub fn start_container(container_name: &str, runtime: &str) -> Result<()> {
         let output = Command::new(runtime)
-            .args(["import", &tarfile.to_string_lossy(), image_tag])
+            .args(["start", container_name])
+            .output()
+            .map_err(|e| ClaudepodError::Docker(format!("Failed to start container: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(ClaudepodError::Docker(format!(
+                "Failed to start container: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Export a container's filesystem as a tar stream
+    pub fn export_container(container_name: &str, runtime: &str) -> Result<ExportStream> {
+        let mut child = Command::new(runtime)
+            .args(["export", container_name])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| ClaudepodError::Docker(format!("Failed to export container: {}", e)))?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        Ok(ExportStream { child, stdout })
+    }
+
+    /// Config of the image a container was created from
+    pub fn image_config(container_name: &str, runtime: &str) -> Result<ImageConfig> {
+        let inspect = |args: &[&str]| -> Result<String> {
+            let output = Command::new(runtime)
+                .args(args)
+                .output()
+                .map_err(|e| ClaudepodError::Docker(format!("Failed to inspect image: {}", e)))?;
+            if !output.status.success() {
+                return Err(ClaudepodError::Docker(format!(
+                    "Failed to inspect image: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                )));
+            }
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        };
+
+        let image_id = inspect(&["inspect", "--format", "{{.Image}}", container_name])?;
+        if image_id.is_empty() {
+            return Err(ClaudepodError::Docker(format!(
+                "No image found for container '{}'",
+                container_name
+            )));
+        }
+        let config = inspect(&[
+            "image",
+            "inspect",
+            "--format",
+            "{{json .Config}}",
+            &image_id,
+        ])?;
+        ImageConfig::from_inspect_json(&config)
+    }
+
+    /// Import a tar stream as a container image, applying Dockerfile
+    /// instructions (`--change`) such as USER or ENTRYPOINT. If reading
+    /// `input` fails, the import is stopped before it sees the end of its
+    /// input, so no image is created from partial data.
+    pub fn import_image<R: Read + ?Sized>(
+        input: &mut R,
+        image_tag: &str,
+        runtime: &str,
+        changes: &[String],
+    ) -> Result<()> {
+        let mut cmd = Command::new(runtime);
+        cmd.arg("import");
+        for change in changes {
+            cmd.args(["--change", change]);
+        }
+        let mut child = cmd
+            .args(["-", image_tag])
+            .stdin(Stdio::piped())
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
-            .output()
+            .spawn()
             .map_err(|e| ClaudepodError::Docker(format!("Failed to import image: {}", e)))?;
 
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        if let Err(e) = io::copy(input, &mut stdin) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(e.into());
+        }
+        drop(stdin);
+
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(ClaudepodError::Docker(format!(
+                "Failed to import image ({})",
+                status
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Get the actual host bindings of a container's published ports,
+    /// as (container port, host address) pairs
+    pub fn port_bindings(container_name: &str, runtime: &str) -> Result<Vec<(String, String)>> {
+        let output = Command::new(runtime)
+            .args([
+                "inspect",
+                "--format",
+                "{{json .NetworkSettings.Ports}}",
+                container_name,
+            ])
+            .output()
+            .map_err(|e| ClaudepodError::Docker(format!("Failed to inspect container: {}", e)))?;
+
         if !output.status.success() {
             return Err(ClaudepodError::Docker(format!(
-                "Failed to import image: {}",
+                "Failed to inspect container: {}",
                 String::from_utf8_lossy(&output.stderr)
             )));
         }
 
-        Ok(())
+        Self::parse_port_bindings(&output.stdout)
+    }
+
+    /// Parse the `.NetworkSettings.Ports` JSON of `inspect`
+    fn parse_port_bindings(json: &[u8]) -> Result<Vec<(String, String)>> {
+        let ports: serde_json::Value = serde_json::from_slice(json)?;
+        let mut bindings = Vec::new();
+
+        if let Some(ports) = ports.as_object() {
+            for (container_port, hosts) in ports {
+                for host in hosts.as_array().into_iter().flatten() {
+                    let ip = host["HostIp"].as_str().unwrap_or("");
+                    let port = host["HostPort"].as_str().unwrap_or("");
+                    let ip = if ip.is_empty() { "0.0.0.0" } else { ip };
+                    bindings.push((container_port.clone(), format!("{}:{}", ip, port)));
+                }
+            }
+        }
+
+        bindings.sort();
+        Ok(bindings)
     }
 
     /// Get the image ID that a container is using
@@ -479,6 +1274,87 @@ impl DockerClient {
 mod tests {
     use super::*;
 
+    /// Apply `import --change` instructions the way the runtime does
+    fn apply_changes(changes: &[String]) -> ImageConfig {
+        let unquote = |value: &str| {
+            let inner = &value[1..value.len() - 1];
+            let mut out = String::new();
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                out.push(if c == '\\' { chars.next().unwrap() } else { c });
+            }
+            out
+        };
+
+        let mut config = ImageConfig::default();
+        for change in changes {
+            let (instruction, arg) = change.split_once(' ').unwrap();
+            match instruction {
+                "USER" => config.user = arg.to_string(),
+                "WORKDIR" => config.working_dir = arg.to_string(),
+                "ENV" => {
+                    let (key, value) = arg.split_once('=').unwrap();
+                    config.env.push(format!("{}={}", key, unquote(value)));
+                }
+                "LABEL" => {
+                    let (key, value) = arg.split_once('=').unwrap();
+                    config.labels.insert(key.to_string(), unquote(value));
+                }
+                "ENTRYPOINT" => config.entrypoint = serde_json::from_str(arg).unwrap(),
+                "CMD" => config.cmd = serde_json::from_str(arg).unwrap(),
+                _ => panic!("unexpected instruction {}", instruction),
+            }
+        }
+        config
+    }
+
+    #[test]
+    fn test_image_config_roundtrip() {
+        let inspect = r#"{
+            "Hostname": "",
+            "User": "code",
+            "Env": [
+                "PATH=/home/code/.npm-global/bin:/usr/local/bin:/usr/bin:/bin",
+                "HOME=/home/code",
+                "GREETING=say \"hi\" to $USER"
+            ],
+            "Entrypoint": ["/usr/local/bin/entrypoint.sh"],
+            "Cmd": ["bash"],
+            "WorkingDir": "/workspace",
+            "Labels": {"dev.claudepod.entrypoint": "2"}
+        }"#;
+        let original = ImageConfig::from_inspect_json(inspect).unwrap();
+        assert_eq!(original.user, "code");
+        assert_eq!(original.env[2], "GREETING=say \"hi\" to $USER");
+
+        let changes = original.changes();
+        assert!(changes.contains(&"USER code".to_string()));
+        assert!(changes.contains(&"LABEL dev.claudepod.entrypoint=\"2\"".to_string()));
+        assert!(changes.contains(&"ENTRYPOINT [\"/usr/local/bin/entrypoint.sh\"]".to_string()));
+        assert_eq!(apply_changes(&changes), original);
+
+        // Null and string forms from other runtime versions
+        let config = ImageConfig::from_inspect_json(
+            r#"{"User": "", "Env": null, "Entrypoint": "/init", "Cmd": null}"#,
+        )
+        .unwrap();
+        assert_eq!(config.entrypoint, vec!["/init"]);
+        assert_eq!(config.changes(), vec!["ENTRYPOINT [\"/init\"]"]);
+    }
+
+    #[test]
+    fn test_image_config_from_profile() {
+        let profile = Profile::default();
+        let config = ImageConfig::from_profile(&profile);
+        assert_eq!(config.user, profile.container.user);
+        assert_eq!(config.entrypoint, vec![ENTRYPOINT_PATH]);
+        assert!(config
+            .env
+            .iter()
+            .any(|e| e.starts_with("PATH=") && e.contains("/.npm-global/bin:")));
+        assert_eq!(apply_changes(&config.changes()), config);
+    }
+
     #[test]
     fn test_get_uid_gid() {
         let uid = DockerClient::get_uid();
@@ -488,4 +1364,387 @@ mod tests {
         assert!(uid > 0 || cfg!(not(unix)));
         assert!(gid > 0 || cfg!(not(unix)));
     }
+
+    #[test]
+    fn test_host_driver_mount_args() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let lib_dir = temp_dir.path().join("usr/lib/x86_64-linux-gnu");
+        std::fs::create_dir_all(&lib_dir).unwrap();
+        std::fs::write(lib_dir.join("libnvoptix.so.550.54"), "").unwrap();
+        std::fs::write(lib_dir.join("libnvidia-rtcore.so.550.54"), "").unwrap();
+        std::fs::write(lib_dir.join("libcuda.so.1"), "").unwrap();
+
+        let gpu = GpuConfig {
+            host_drivers: "mount".to_string(),
+            host_driver_globs: vec![
+                format!("{}/libnvoptix.so.*", lib_dir.display()),
+                format!("{}/libnvidia-rtcore.*", lib_dir.display()),
+            ],
+            ..Default::default()
+        };
+        let files = gpu.resolve_host_drivers().unwrap();
+
+        let rtcore = lib_dir.join("libnvidia-rtcore.so.550.54");
+        let optix = lib_dir.join("libnvoptix.so.550.54");
+        assert_eq!(
+            DockerClient::host_driver_mount_args(&files),
+            vec![
+                format!("--volume={}:{}:ro", rtcore.display(), rtcore.display()),
+                format!("--volume={}:{}:ro", optix.display(), optix.display()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_security_args() {
+        let container = ContainerConfig::default();
+        let docker = DockerConfig::default();
+
+        let args =
+            DockerClient::security_args(&SecurityConfig::default(), &container, &docker, "podman")
+                .unwrap();
+        assert!(args.is_empty());
+
+        let security = SecurityConfig {
+            cap_drop: vec!["ALL".to_string()],
+            cap_add: vec!["CHOWN".to_string()],
+            read_only_root: true,
+            no_new_privileges: true,
+            seccomp: Some("unconfined".to_string()),
+            sudo: false,
+        };
+
+        let args = DockerClient::security_args(&security, &container, &docker, "docker").unwrap();
+        assert_eq!(
+            args,
+            vec![
+                "--cap-drop=ALL",
+                "--cap-add=CHOWN",
+                "--security-opt=no-new-privileges",
+                "--security-opt=seccomp=unconfined",
+                "--read-only",
+                "--tmpfs=/tmp:rw,mode=1777",
+                "--tmpfs=/var/tmp:rw,mode=1777",
+                "--volume=/home/code",
+            ]
+        );
+
+        // Podman mounts /tmp itself, and a home the profile already mounts is left alone
+        let mut docker = docker;
+        docker.tmpfs.push(crate::profile::TmpfsMount {
+            path: "/home/code/".to_string(),
+            readonly: false,
+            size: "1g".to_string(),
+        });
+        let args = DockerClient::security_args(&security, &container, &docker, "podman").unwrap();
+        assert!(!args.iter().any(|a| a.starts_with("--tmpfs=/tmp")));
+        assert!(!args.iter().any(|a| a.starts_with("--volume=")));
+    }
+
+    #[test]
+    fn test_exec_command_line() {
+        let mut exec = ExecOptions {
+            argv: vec!["cargo".to_string(), "test".to_string()],
+            env: vec![],
+            working_dir: PathBuf::from("/project"),
+            user: None,
+            pre: None,
+            post: None,
+        };
+        assert_eq!(exec.command_line(), vec!["cargo", "test"]);
+
+        exec.pre = Some("mkdir -p build".to_string());
+        exec.post = Some("echo done".to_string());
+        assert_eq!(
+            exec.command_line(),
+            vec![
+                "bash",
+                "-c",
+                "set -e\nmkdir -p build\nset +e\n\"$@\"\nstatus=$?\necho done\nexit $status",
+                "claudepod",
+                "cargo",
+                "test",
+            ]
+        );
+
+        // The wrapper keeps the command's exit code and always runs post
+        exec.argv = vec!["sh".to_string(), "-c".to_string(), "exit 3".to_string()];
+        exec.pre = Some("true".to_string());
+        exec.post = Some("echo post".to_string());
+        let output = Command::new(&exec.command_line()[0])
+            .args(&exec.command_line()[1..])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "post\n");
+
+        // A failing pre aborts before the command
+        exec.pre = Some("false".to_string());
+        let output = Command::new(&exec.command_line()[0])
+            .args(&exec.command_line()[1..])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    fn test_host_hook() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let hook = HookConfig {
+            command: "echo \"$CLAUDEPOD_CONTAINER $CLAUDEPOD_EXIT_CODE $PWD\" > out.txt"
+                .to_string(),
+            host: true,
+        };
+
+        DockerClient::run_hooks(
+            &[hook],
+            "podman",
+            "claudepod-test",
+            temp_dir.path(),
+            Some(3),
+            &[],
+        )
+        .unwrap();
+
+        let out = std::fs::read_to_string(temp_dir.path().join("out.txt")).unwrap();
+        let pwd = temp_dir.path().canonicalize().unwrap();
+        assert_eq!(out, format!("claudepod-test 3 {}\n", pwd.display()));
+
+        // A failing hook stops the list
+        let hooks = vec![
+            HookConfig {
+                command: "exit 2".to_string(),
+                host: true,
+            },
+            HookConfig {
+                command: "touch never".to_string(),
+                host: true,
+            },
+        ];
+        assert!(DockerClient::run_hooks(
+            &hooks,
+            "podman",
+            "claudepod-test",
+            temp_dir.path(),
+            None,
+            &[]
+        )
+        .is_err());
+        assert!(!temp_dir.path().join("never").exists());
+    }
+
+    #[test]
+    fn test_container_hook_command() {
+        let hook = HookConfig {
+            command: "pip install -e .".to_string(),
+            host: false,
+        };
+        let cmd = DockerClient::hook_command(
+            &hook,
+            "docker",
+            "claudepod-test",
+            Path::new("/project"),
+            None,
+            &[(
+                "HTTPS_PROXY".to_string(),
+                "http://10.89.0.1:4000".to_string(),
+            )],
+            true,
+        );
+        assert_eq!(cmd.get_program(), "docker");
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy()).collect();
+        assert_eq!(
+            args,
+            vec![
+                "exec",
+                "-w",
+                "/project",
+                "-e",
+                "CLAUDEPOD_PROJECT_DIR=/project",
+                "-e",
+                "CLAUDEPOD_CONTAINER=claudepod-test",
+                "-e",
+                "HTTPS_PROXY=http://10.89.0.1:4000",
+                "claudepod-test",
+                ENTRYPOINT_PATH,
+                "bash",
+                "-c",
+                "pip install -e .",
+            ]
+        );
+
+        // Older images run the hook without their entrypoint
+        let cmd = DockerClient::hook_command(
+            &hook,
+            "docker",
+            "claudepod-test",
+            Path::new("/project"),
+            None,
+            &[],
+            false,
+        );
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy()).collect();
+        assert_eq!(
+            args[args.len() - 4..],
+            ["claudepod-test", "bash", "-c", "pip install -e ."]
+        );
+    }
+
+    #[test]
+    fn test_export_and_import_streams() {
+        // `false export ...` fails without output: the stream must not end cleanly
+        let mut export = DockerClient::export_container("claudepod-test", "false").unwrap();
+        let mut data = Vec::new();
+        assert!(export.read_to_end(&mut data).is_err());
+
+        // A failing input stops the import instead of completing it
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("checksum mismatch"))
+            }
+        }
+        let err = DockerClient::import_image(&mut Failing, "claudepod:test", "true", &[]);
+        assert!(err.unwrap_err().to_string().contains("checksum mismatch"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_removes_container_when_on_create_fails() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A runtime that knows no containers, logs its subcommands and fails
+        // every exec (so the on_create hook fails)
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let log = temp_dir.path().join("log");
+        let runtime = temp_dir.path().join("runtime");
+        std::fs::write(
+            &runtime,
+            format!(
+                "#!/bin/sh\necho \"$1\" >>'{}'\n[ \"$1\" != exec ]\n",
+                log.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&runtime, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut profile = Profile::default();
+        profile.docker.container_runtime = runtime.to_string_lossy().to_string();
+        profile.hooks.on_create.push(HookConfig {
+            command: "false".to_string(),
+            host: false,
+        });
+        let exec = ExecOptions {
+            argv: vec!["true".to_string()],
+            env: vec![],
+            working_dir: temp_dir.path().to_path_buf(),
+            user: None,
+            pre: None,
+            post: None,
+        };
+
+        let result = DockerClient::run(
+            &ContainerSpec::from_profile(&profile),
+            "claudepod-test",
+            "claudepod:test",
+            &exec,
+            temp_dir.path(),
+        );
+        assert!(result.is_err());
+
+        // Created, started, hook failed, removed; the command never ran
+        let calls = std::fs::read_to_string(&log).unwrap();
+        let calls: Vec<_> = calls
+            .lines()
+            .filter(|c| *c != "ps" && *c != "inspect")
+            .collect();
+        assert_eq!(calls, vec!["create", "start", "exec", "rm"]);
+    }
+
+    #[test]
+    fn test_port_args() {
+        let mut v6 = PortMapping::parse("0:8000/udp").unwrap();
+        v6.address = "::1".to_string();
+        let ports = vec![PortMapping::parse("8080:3000").unwrap(), v6];
+        assert_eq!(
+            DockerClient::port_args(&ports),
+            vec![
+                "--publish=127.0.0.1:8080:3000/tcp",
+                "--publish=[::1]::8000/udp",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_port_bindings() {
+        let json = br#"{"3000/tcp":[{"HostIp":"127.0.0.1","HostPort":"3000"}],
+            "8000/udp":[{"HostIp":"","HostPort":"41234"}],"9000/tcp":null}"#;
+        assert_eq!(
+            DockerClient::parse_port_bindings(json).unwrap(),
+            vec![
+                ("3000/tcp".to_string(), "127.0.0.1:3000".to_string()),
+                ("8000/udp".to_string(), "0.0.0.0:41234".to_string()),
+            ]
+        );
+        assert!(DockerClient::parse_port_bindings(b"null")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_device_args() {
+        let devices = vec![
+            DeviceMount {
+                host: "/dev/fuse".to_string(),
+                container: None,
+                permissions: "rwm".to_string(),
+            },
+            DeviceMount {
+                host: "/dev/ttyUSB0".to_string(),
+                container: Some("/dev/ttyACM0".to_string()),
+                permissions: "rw".to_string(),
+            },
+        ];
+        assert_eq!(
+            DockerClient::device_args(&devices),
+            vec![
+                "--device=/dev/fuse:/dev/fuse:rwm",
+                "--device=/dev/ttyUSB0:/dev/ttyACM0:rw",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resource_args() {
+        assert!(DockerClient::resource_args(&ResourcesConfig::default()).is_empty());
+
+        let mut resources = ResourcesConfig {
+            cpus: Some(1.5),
+            memory: Some("8G".to_string()),
+            memory_swap: Some("-1".to_string()),
+            pids_limit: Some(2048),
+            shm_size: Some("1g".to_string()),
+            ..Default::default()
+        };
+        resources
+            .ulimits
+            .insert("nofile".to_string(), "1024:4096".to_string());
+        resources
+            .ulimits
+            .insert("core".to_string(), "unlimited".to_string());
+
+        assert_eq!(
+            DockerClient::resource_args(&resources),
+            vec![
+                "--cpus=1.5",
+                "--memory=8g",
+                "--memory-swap=-1",
+                "--pids-limit=2048",
+                "--shm-size=1g",
+                "--ulimit=core=-1",
+                "--ulimit=nofile=1024:4096",
+            ]
+        );
+    }
 }
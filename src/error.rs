@@ -24,6 +24,9 @@ pub enum ClaudepodError {
     #[error("Docker command failed: {0}")]
     Docker(String),
 
+    #[error("Git command failed: {0}")]
+    Git(String),
+
     #[error("File not found: {0}")]
     FileNotFound(String),
 
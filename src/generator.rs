@@ -1,5 +1,5 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tera::{Context, Tera};
 
 use crate::profile::Profile;
@@ -8,6 +8,19 @@ use crate::error::Result;
 const DOCKERFILE_TEMPLATE: &str = include_str!("../templates/Dockerfile.tera");
 const ENTRYPOINT_TEMPLATE: &str = include_str!("../templates/entrypoint.sh.tera");
 
+/// Build context subdirectory that host driver files are staged into
+const HOST_DRIVERS_DIR: &str = "host-drivers";
+
+/// Location of the generated entrypoint inside the image. Containers run it
+/// as their main process and every exec is routed through it.
+pub const ENTRYPOINT_PATH: &str = "/usr/local/bin/entrypoint.sh";
+
+/// Image label recording the entrypoint's version. Images without it have
+/// the older entrypoint, which can't wrap execs (it prints to stdout and
+/// appends to .bashrc on every run).
+pub const ENTRYPOINT_LABEL: &str = "dev.claudepod.entrypoint";
+pub const ENTRYPOINT_VERSION: &str = "2";
+
 pub struct Generator {
     tera: Tera,
 }
@@ -29,16 +42,13 @@ impl Generator {
         // Create output directory if it doesn't exist
         fs::create_dir_all(output_dir)?;
 
-        // Generate context for templates
-        let context = self.build_context(config);
+        let (dockerfile_content, entrypoint_content) = self.render(config)?;
 
         // Generate Dockerfile
-        let dockerfile_content = self.tera.render("Dockerfile", &context)?;
         let dockerfile_path = output_dir.join("Dockerfile");
         fs::write(&dockerfile_path, dockerfile_content)?;
 
         // Generate entrypoint.sh
-        let entrypoint_content = self.tera.render("entrypoint.sh", &context)?;
         let entrypoint_path = output_dir.join("entrypoint.sh");
         fs::write(&entrypoint_path, entrypoint_content)?;
 
@@ -51,12 +61,65 @@ impl Generator {
             fs::set_permissions(&entrypoint_path, perms)?;
         }
 
+        // Stage host driver files into the build context (or clear stale ones)
+        let drivers_dir = output_dir.join(HOST_DRIVERS_DIR);
+        if drivers_dir.exists() {
+            fs::remove_dir_all(&drivers_dir)?;
+        }
+        if config.gpu.host_drivers == "copy" {
+            let files = config.gpu.resolve_host_drivers()?;
+            if files.is_empty() {
+                eprintln!("Warning: gpu.host_drivers = \"copy\" but no host driver files matched");
+            }
+            self.stage_host_drivers(&files, &drivers_dir)?;
+            println!(
+                "Staged {} host driver file(s) at: {}",
+                files.len(),
+                drivers_dir.display()
+            );
+        }
+
         println!("Generated Dockerfile at: {}", dockerfile_path.display());
         println!("Generated entrypoint.sh at: {}", entrypoint_path.display());
 
         Ok(())
     }
 
+    /// Render the Dockerfile and entrypoint script for a configuration
+    pub fn render(&self, config: &Profile) -> Result<(String, String)> {
+        let context = self.build_context(config);
+        Ok((
+            self.tera.render("Dockerfile", &context)?,
+            self.tera.render("entrypoint.sh", &context)?,
+        ))
+    }
+
+    /// Copy host files into `staging_dir`, mirroring their absolute paths and
+    /// keeping symlinks as symlinks (like `cp -Rdp`)
+    fn stage_host_drivers(&self, files: &[PathBuf], staging_dir: &Path) -> Result<()> {
+        fs::create_dir_all(staging_dir)?;
+
+        for file in files {
+            let relative = file.strip_prefix("/").unwrap_or(file);
+            let target = staging_dir.join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let metadata = fs::symlink_metadata(file)?;
+            #[cfg(unix)]
+            if metadata.file_type().is_symlink() {
+                std::os::unix::fs::symlink(fs::read_link(file)?, &target)?;
+                continue;
+            }
+
+            fs::copy(file, &target)?;
+            fs::set_permissions(&target, metadata.permissions())?;
+        }
+
+        Ok(())
+    }
+
     /// Build template context from configuration
     fn build_context(&self, config: &Profile) -> Context {
         let mut context = Context::new();
@@ -66,6 +129,9 @@ impl Generator {
         context.insert("user", &config.container.user);
         context.insert("home_dir", &config.container.home_dir);
         context.insert("work_dir", &config.container.work_dir);
+        context.insert("entrypoint_path", ENTRYPOINT_PATH);
+        context.insert("entrypoint_label", ENTRYPOINT_LABEL);
+        context.insert("entrypoint_version", ENTRYPOINT_VERSION);
 
         // Use apt packages (already a single list)
         let mut apt_packages = config.dependencies.apt.clone();
@@ -99,6 +165,13 @@ impl Generator {
         context.insert("git_user_name", &config.git.user_name);
         context.insert("git_user_email", &config.git.user_email);
 
+        // Security
+        context.insert("sudo", &config.security.sudo);
+
+        // Host GPU driver files
+        context.insert("copy_host_drivers", &(config.gpu.host_drivers == "copy"));
+        context.insert("host_drivers_dir", HOST_DRIVERS_DIR);
+
         // Shell config
         context.insert("aliases", &config.shell.aliases);
         context.insert("history_search", &config.shell.history_search);
@@ -155,5 +228,85 @@ mod tests {
         let dockerfile_content = fs::read_to_string(temp_dir.path().join("Dockerfile")).unwrap();
         assert!(dockerfile_content.contains("FROM"));
         assert!(dockerfile_content.contains(&config.container.base_image));
+        assert!(dockerfile_content.contains(&format!("ENTRYPOINT [\"{}\"]", ENTRYPOINT_PATH)));
+        assert!(dockerfile_content.contains(&format!(
+            "LABEL {}=\"{}\"",
+            ENTRYPOINT_LABEL, ENTRYPOINT_VERSION
+        )));
+    }
+
+    #[test]
+    fn test_entrypoint_init_is_guarded() {
+        let generator = Generator::new().unwrap();
+        let config = Profile::default();
+        let temp_dir = TempDir::new().unwrap();
+        generator.generate(&config, temp_dir.path()).unwrap();
+
+        let entrypoint = fs::read_to_string(temp_dir.path().join("entrypoint.sh")).unwrap();
+        assert!(entrypoint.contains("if [ ! -f \"$INIT_MARKER\" ]; then"));
+        assert!(entrypoint.contains("flock 9"));
+        assert!(entrypoint.contains("grep -qxF 'source ~/.env'"));
+        assert!(!entrypoint.contains("echo \"Ready.\""));
+        assert!(entrypoint.ends_with("source /home/code/.env\nexec -- \"$@\"\n"));
+    }
+
+    #[test]
+    fn test_sudo_can_be_disabled() {
+        let generator = Generator::new().unwrap();
+        let mut config = Profile::default();
+        let temp_dir = TempDir::new().unwrap();
+
+        generator.generate(&config, temp_dir.path()).unwrap();
+        let dockerfile = fs::read_to_string(temp_dir.path().join("Dockerfile")).unwrap();
+        assert!(dockerfile.contains("NOPASSWD:ALL"));
+
+        config.security.sudo = false;
+        generator.generate(&config, temp_dir.path()).unwrap();
+        let dockerfile = fs::read_to_string(temp_dir.path().join("Dockerfile")).unwrap();
+        assert!(!dockerfile.contains("/etc/sudoers"));
+        assert!(dockerfile.contains("useradd -m -s /bin/bash -u $USER_UID -g $GROUP_NAME code\n"));
+    }
+
+    #[test]
+    fn test_copy_host_drivers() {
+        let generator = Generator::new().unwrap();
+        let host_dir = TempDir::new().unwrap();
+        let lib_dir = host_dir.path().join("usr/lib/x86_64-linux-gnu");
+        fs::create_dir_all(&lib_dir).unwrap();
+        fs::write(lib_dir.join("libnvoptix.so.550.54"), "optix").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("libnvoptix.so.550.54", lib_dir.join("libnvoptix.so.1"))
+            .unwrap();
+
+        let mut config = Profile::default();
+        config.gpu.host_drivers = "copy".to_string();
+        config.gpu.host_driver_globs = vec![format!("{}/libnvoptix.so.*", lib_dir.display())];
+
+        let build_dir = TempDir::new().unwrap();
+        generator.generate(&config, build_dir.path()).unwrap();
+
+        let staged_lib_dir = build_dir
+            .path()
+            .join(HOST_DRIVERS_DIR)
+            .join(lib_dir.strip_prefix("/").unwrap());
+        assert_eq!(
+            fs::read_to_string(staged_lib_dir.join("libnvoptix.so.550.54")).unwrap(),
+            "optix"
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            fs::read_link(staged_lib_dir.join("libnvoptix.so.1")).unwrap(),
+            PathBuf::from("libnvoptix.so.550.54")
+        );
+
+        let dockerfile = fs::read_to_string(build_dir.path().join("Dockerfile")).unwrap();
+        assert!(dockerfile.contains("COPY host-drivers/ /\nRUN ldconfig"));
+
+        // Switching back to "none" removes the staged files
+        config.gpu.host_drivers = "none".to_string();
+        generator.generate(&config, build_dir.path()).unwrap();
+        assert!(!build_dir.path().join(HOST_DRIVERS_DIR).exists());
+        let dockerfile = fs::read_to_string(build_dir.path().join("Dockerfile")).unwrap();
+        assert!(!dockerfile.contains("host-drivers"));
     }
 }
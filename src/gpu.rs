@@ -0,0 +1,343 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::profile::{DockerConfig, GpuConfig, GpuToggle};
+
+/// Directories (relative to the host root) where CDI specs are installed
+const CDI_SPEC_DIRS: [&str; 2] = ["etc/cdi", "var/run/cdi"];
+
+/// CDI device kind generated by `nvidia-ctk cdi generate`
+const NVIDIA_CDI_KIND: &str = "nvidia.com/gpu";
+
+/// Runtime flags for the configured GPU, plus warnings to show the user
+#[derive(Debug, Default, PartialEq)]
+pub struct GpuArgs {
+    pub args: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Map a host path like "/dev/kfd" below `root` (which is "/" outside tests)
+fn host_path(root: &Path, path: &str) -> PathBuf {
+    root.join(path.trim_start_matches('/'))
+}
+
+/// Check whether a CDI spec declaring `kind` exists under `root`
+pub fn has_cdi_spec(root: &Path, kind: &str) -> bool {
+    CDI_SPEC_DIRS.iter().any(|dir| {
+        let Ok(entries) = fs::read_dir(root.join(dir)) else {
+            return false;
+        };
+        entries.flatten().any(|entry| {
+            let path = entry.path();
+            let is_spec = matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("yaml" | "yml" | "json")
+            );
+            is_spec
+                && fs::read_to_string(&path)
+                    .map(|content| content.contains(kind))
+                    .unwrap_or(false)
+        })
+    })
+}
+
+/// Check whether the host has the device nodes a vendor's GPU needs
+pub fn host_has_device(root: &Path, vendor: &str) -> bool {
+    match vendor {
+        "nvidia" => host_path(root, "/dev/nvidiactl").exists(),
+        "amd" => host_path(root, "/dev/kfd").exists() && host_path(root, "/dev/dri").exists(),
+        "intel" => host_path(root, "/dev/dri").exists(),
+        _ => false,
+    }
+}
+
+/// NVIDIA device selectors: `gpu.devices`, or the legacy `docker.gpu_driver`
+fn nvidia_selectors(docker: &DockerConfig, gpu: &GpuConfig) -> Vec<String> {
+    if !gpu.devices.is_empty() {
+        return gpu.devices.clone();
+    }
+    docker
+        .gpu_driver
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty() && s != "all")
+        .collect()
+}
+
+/// Existing /dev/nvidia* nodes for the selected indices (all GPUs if none)
+fn nvidia_device_nodes(root: &Path, selectors: &[String]) -> Vec<String> {
+    let mut nodes = Vec::new();
+    for name in [
+        "nvidiactl",
+        "nvidia-uvm",
+        "nvidia-uvm-tools",
+        "nvidia-modeset",
+    ] {
+        if host_path(root, &format!("/dev/{}", name)).exists() {
+            nodes.push(format!("/dev/{}", name));
+        }
+    }
+
+    let mut gpus: Vec<u32> = match fs::read_dir(host_path(root, "/dev")) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|e| {
+                e.file_name()
+                    .to_str()
+                    .and_then(|n| n.strip_prefix("nvidia"))
+                    .and_then(|n| n.parse().ok())
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    gpus.sort();
+    if !selectors.is_empty() {
+        gpus.retain(|i| selectors.contains(&i.to_string()));
+    }
+    nodes.extend(gpus.iter().map(|i| format!("/dev/nvidia{}", i)));
+    nodes
+}
+
+/// Group IDs owning the given device nodes (directories contribute their entries)
+fn device_gids(root: &Path, devices: &[String]) -> BTreeSet<u32> {
+    let mut gids = BTreeSet::new();
+    for device in devices {
+        let path = host_path(root, device);
+        if path.is_dir() {
+            for entry in fs::read_dir(&path).into_iter().flatten().flatten() {
+                if let Ok(meta) = entry.metadata() {
+                    gids.insert(meta.gid());
+                }
+            }
+        } else if let Ok(meta) = fs::metadata(&path) {
+            gids.insert(meta.gid());
+        }
+    }
+    gids
+}
+
+/// Build the GPU flags for `create`, detecting devices and CDI specs below `root`
+pub fn gpu_args(docker: &DockerConfig, gpu: &GpuConfig, root: &Path) -> GpuArgs {
+    let mut out = GpuArgs::default();
+    let vendor = gpu.vendor.as_str();
+    let podman = docker.container_runtime == "podman";
+
+    match docker.enable_gpu {
+        GpuToggle::Enabled(false) => return out,
+        GpuToggle::Enabled(true) => {
+            if !host_has_device(root, vendor) {
+                out.warnings.push(format!(
+                    "enable_gpu = true but no {} GPU device was found on this host; starting without GPU",
+                    vendor
+                ));
+                return out;
+            }
+        }
+        GpuToggle::Mode(_) => {
+            if !host_has_device(root, vendor) {
+                return out;
+            }
+        }
+    }
+
+    match vendor {
+        "nvidia" => {
+            let selectors = nvidia_selectors(docker, gpu);
+            if podman {
+                if has_cdi_spec(root, NVIDIA_CDI_KIND) {
+                    if selectors.is_empty() {
+                        out.args.push(format!("--device={}=all", NVIDIA_CDI_KIND));
+                    }
+                    for selector in &selectors {
+                        out.args
+                            .push(format!("--device={}={}", NVIDIA_CDI_KIND, selector));
+                    }
+                } else {
+                    out.warnings.push(format!(
+                        "No CDI spec for {} under /etc/cdi or /var/run/cdi \
+                         (run `nvidia-ctk cdi generate`); passing /dev/nvidia* device nodes \
+                         without driver libraries",
+                        NVIDIA_CDI_KIND
+                    ));
+                    for node in nvidia_device_nodes(root, &selectors) {
+                        out.args.push(format!("--device={}", node));
+                    }
+                }
+            } else if selectors.is_empty() {
+                out.args.push("--gpus=all".to_string());
+            } else {
+                // Quoted so docker does not split the device list as CSV
+                out.args
+                    .push(format!("--gpus=\"device={}\"", selectors.join(",")));
+            }
+        }
+        "amd" | "intel" => {
+            let mut devices = Vec::new();
+            if vendor == "amd" {
+                devices.push("/dev/kfd".to_string());
+            }
+            if gpu.devices.is_empty() {
+                devices.push("/dev/dri".to_string());
+            } else {
+                devices.extend(gpu.devices.iter().cloned());
+            }
+
+            for device in &devices {
+                out.args.push(format!("--device={}", device));
+            }
+
+            // The container user needs the device groups (video/render)
+            if podman {
+                out.args.push("--group-add=keep-groups".to_string());
+            } else {
+                for gid in device_gids(root, &devices) {
+                    out.args.push(format!("--group-add={}", gid));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn touch(root: &Path, path: &str, content: &str) {
+        let path = host_path(root, path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn config(runtime: &str, enable_gpu: GpuToggle, vendor: &str) -> (DockerConfig, GpuConfig) {
+        let docker = DockerConfig {
+            container_runtime: runtime.to_string(),
+            enable_gpu,
+            ..Default::default()
+        };
+        let gpu = GpuConfig {
+            vendor: vendor.to_string(),
+            ..Default::default()
+        };
+        (docker, gpu)
+    }
+
+    #[test]
+    fn test_has_cdi_spec() {
+        let root = TempDir::new().unwrap();
+        assert!(!has_cdi_spec(root.path(), NVIDIA_CDI_KIND));
+
+        touch(
+            root.path(),
+            "/etc/cdi/other.yaml",
+            "kind: vendor.com/device",
+        );
+        touch(
+            root.path(),
+            "/var/run/cdi/nvidia.txt",
+            "kind: nvidia.com/gpu",
+        );
+        assert!(!has_cdi_spec(root.path(), NVIDIA_CDI_KIND));
+
+        touch(
+            root.path(),
+            "/var/run/cdi/nvidia.yaml",
+            "kind: nvidia.com/gpu",
+        );
+        assert!(has_cdi_spec(root.path(), NVIDIA_CDI_KIND));
+    }
+
+    #[test]
+    fn test_auto_without_device() {
+        let root = TempDir::new().unwrap();
+        for vendor in ["nvidia", "amd", "intel"] {
+            let (docker, gpu) = config("podman", GpuToggle::default(), vendor);
+            assert_eq!(gpu_args(&docker, &gpu, root.path()), GpuArgs::default());
+        }
+
+        // Explicitly enabled: skipped with a warning instead of failing create
+        let (docker, gpu) = config("docker", GpuToggle::Enabled(true), "nvidia");
+        let result = gpu_args(&docker, &gpu, root.path());
+        assert!(result.args.is_empty());
+        assert_eq!(result.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_nvidia_args() {
+        let root = TempDir::new().unwrap();
+        touch(root.path(), "/dev/nvidiactl", "");
+        touch(root.path(), "/dev/nvidia0", "");
+        touch(root.path(), "/dev/nvidia1", "");
+
+        let (mut docker, mut gpu) = config("docker", GpuToggle::default(), "nvidia");
+        assert_eq!(
+            gpu_args(&docker, &gpu, root.path()).args,
+            vec!["--gpus=all"]
+        );
+
+        gpu.devices = vec!["0".to_string(), "1".to_string()];
+        assert_eq!(
+            gpu_args(&docker, &gpu, root.path()).args,
+            vec!["--gpus=\"device=0,1\""]
+        );
+
+        // Podman without CDI spec falls back to the device nodes
+        docker.container_runtime = "podman".to_string();
+        gpu.devices = vec!["1".to_string()];
+        let result = gpu_args(&docker, &gpu, root.path());
+        assert_eq!(
+            result.args,
+            vec!["--device=/dev/nvidiactl", "--device=/dev/nvidia1"]
+        );
+        assert_eq!(result.warnings.len(), 1);
+
+        // With a CDI spec, the legacy gpu_driver selects the device
+        touch(root.path(), "/etc/cdi/nvidia.yaml", "kind: nvidia.com/gpu");
+        gpu.devices.clear();
+        docker.gpu_driver = "GPU-1234".to_string();
+        let result = gpu_args(&docker, &gpu, root.path());
+        assert_eq!(result.args, vec!["--device=nvidia.com/gpu=GPU-1234"]);
+        assert!(result.warnings.is_empty());
+
+        docker.enable_gpu = GpuToggle::Enabled(false);
+        assert_eq!(gpu_args(&docker, &gpu, root.path()), GpuArgs::default());
+    }
+
+    #[test]
+    fn test_amd_intel_args() {
+        let root = TempDir::new().unwrap();
+        touch(root.path(), "/dev/dri/renderD128", "");
+
+        // AMD needs /dev/kfd as well
+        let (docker, gpu) = config("podman", GpuToggle::default(), "amd");
+        assert!(gpu_args(&docker, &gpu, root.path()).args.is_empty());
+
+        touch(root.path(), "/dev/kfd", "");
+        assert_eq!(
+            gpu_args(&docker, &gpu, root.path()).args,
+            vec![
+                "--device=/dev/kfd",
+                "--device=/dev/dri",
+                "--group-add=keep-groups"
+            ]
+        );
+
+        let (docker, mut gpu) = config("docker", GpuToggle::Enabled(true), "intel");
+        gpu.devices = vec!["/dev/dri/renderD128".to_string()];
+        let gid = fs::metadata(host_path(root.path(), "/dev/dri/renderD128"))
+            .unwrap()
+            .gid();
+        assert_eq!(
+            gpu_args(&docker, &gpu, root.path()).args,
+            vec![
+                "--device=/dev/dri/renderD128".to_string(),
+                format!("--group-add={}", gid)
+            ]
+        );
+    }
+}
@@ -1,24 +1,38 @@
+mod bundle;
+mod completions;
 mod docker;
 mod error;
 mod generator;
+mod gpu;
+mod migrations;
+mod network;
 mod paths;
 mod profile;
+mod remote;
+mod sessions;
 mod storage;
+mod workspace;
 
 use chrono::Utc;
-use clap::{Parser, Subcommand};
-use std::fs;
-use std::io::{self, Write};
-use std::path::PathBuf;
-
-use docker::DockerClient;
+use clap::{CommandFactory, Parser, Subcommand};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use bundle::{Compression, Manifest};
+use completions::{CandidateKind, CompletionShell};
+use docker::{ContainerSpec, DockerClient, ExecOptions, ImageConfig};
 use error::{ClaudepodError, Result};
 use generator::Generator;
-use profile::{Profile, VolumeMount};
+use network::EgressProxy;
+use profile::{FieldDiff, PortMapping, Profile, VolumeMount};
 use storage::{
     compute_project_id, container_name, delete_project_data, generate_uuid, load_project_data,
-    save_project_data, ContainerInfo, ProjectData, ProjectEntry, ProjectsIndex,
+    save_project_data, snapshot_tag, validate_snapshot_name, ContainerInfo, Drift, ProjectData,
+    ProjectEntry, ProjectsIndex, Snapshot,
 };
+use workspace::{Change, ChangeKind, Workspace};
 
 #[derive(Parser)]
 #[command(name = "claudepod")]
@@ -52,6 +66,10 @@ enum Commands {
 
     /// Run a command in the container for current project
     Run {
+        /// Set an environment variable for this run (KEY=VAL, or KEY to pass from host)
+        #[arg(short = 'e', long = "env", value_name = "KEY[=VAL]")]
+        env: Vec<String>,
+
         /// Command name (defined in profile) or executable
         command: Option<String>,
 
@@ -60,6 +78,32 @@ enum Commands {
         args: Vec<String>,
     },
 
+    /// Execute a program in the container as-is (no profile command resolution)
+    Exec {
+        /// User to run as (name or UID[:GID])
+        #[arg(short, long, conflicts_with = "root")]
+        user: Option<String>,
+
+        /// Run as root
+        #[arg(long)]
+        root: bool,
+
+        /// Working directory (relative paths resolve against the current directory)
+        #[arg(short, long)]
+        workdir: Option<PathBuf>,
+
+        /// Set an environment variable (KEY=VAL, or KEY to pass from host)
+        #[arg(short = 'e', long = "env", value_name = "KEY[=VAL]")]
+        env: Vec<String>,
+
+        /// Program to execute
+        program: String,
+
+        /// Arguments to pass to the program
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
     /// Remove container(s) for current project
     Reset {
         /// Remove all containers for this project
@@ -70,15 +114,19 @@ enum Commands {
     /// List containers in current project
     List,
 
-    /// Export the container filesystem to a tar file
+    /// Save the container (filesystem, frozen config and image recipe) to a bundle
     Save {
-        /// Output file path (default: <container_name>.tar in current directory)
+        /// Output file path (default: <container_name>.tar in current directory), or - for stdout
         output: Option<String>,
+
+        /// Compression (default: from the output file extension, .tar.gz or .tar.zst)
+        #[arg(long, value_enum)]
+        compress: Option<Compression>,
     },
 
-    /// Load a container from a saved tar file
+    /// Load a container from a saved bundle (or a plain exported tar file)
     Load {
-        /// Path to the tar file to import
+        /// Path to the bundle to import, or - for stdin
         tarfile: String,
 
         /// Profile to use if no config in tar file
@@ -86,6 +134,103 @@ enum Commands {
         profile: String,
     },
 
+    /// Copy the container to another machine over ssh (save | ssh host claudepod load -)
+    Push {
+        /// Remote host ([user@]host, as understood by ssh)
+        host: String,
+
+        /// Remote project directory (default: the same path relative to the home directory)
+        #[arg(long)]
+        dir: Option<String>,
+
+        /// Compression of the streamed bundle
+        #[arg(long, value_enum, default_value = "zstd")]
+        compress: Compression,
+
+        /// claudepod executable on the remote host
+        #[arg(long, default_value = "claudepod")]
+        remote_claudepod: String,
+    },
+
+    /// Copy a container from another machine over ssh (ssh host claudepod save - | load -)
+    Pull {
+        /// Remote host ([user@]host, as understood by ssh)
+        host: String,
+
+        /// Remote project directory (default: the same path relative to the home directory)
+        #[arg(long)]
+        dir: Option<String>,
+
+        /// Compression of the streamed bundle
+        #[arg(long, value_enum, default_value = "zstd")]
+        compress: Compression,
+
+        /// claudepod executable on the remote host
+        #[arg(long, default_value = "claudepod")]
+        remote_claudepod: String,
+    },
+
+    /// Snapshot the container's current state (e.g. before something risky)
+    Snapshot {
+        /// Snapshot name (default: the current time, e.g. 20250101-120000)
+        name: Option<String>,
+
+        /// Description of the snapshot
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+
+    /// Recreate the container from a snapshot (its current state is discarded)
+    Restore {
+        /// Snapshot to restore
+        name: String,
+    },
+
+    /// Return the container to its state before a recent session (needs session.auto_snapshot)
+    Rollback {
+        /// Number of sessions to go back (1 undoes the last session)
+        #[arg(default_value_t = 1)]
+        sessions: usize,
+    },
+
+    /// List the project's git-checkpointed sessions (needs session.git_checkpoint)
+    Sessions,
+
+    /// Act on a git-checkpointed session
+    Session {
+        #[command(subcommand)]
+        action: SessionAction,
+    },
+
+    /// List or remove the container's snapshots
+    Snapshots {
+        #[command(subcommand)]
+        action: Option<SnapshotsAction>,
+    },
+
+    /// Show the changes pending in the overlay workspace (needs docker.workspace_mode = "overlay")
+    Diff {
+        /// List the changed files instead of showing the diff
+        #[arg(long)]
+        stat: bool,
+    },
+
+    /// Copy changes from the overlay workspace into the project
+    Apply {
+        /// Files or directories to apply (default: all changes)
+        paths: Vec<String>,
+
+        /// Apply changes to files that were also changed in the project
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Drop changes in the overlay workspace
+    Discard {
+        /// Files or directories to discard (default: all changes)
+        paths: Vec<String>,
+    },
+
     /// List all tracked projects
     Projects {
         /// Show detailed container information
@@ -93,7 +238,7 @@ enum Commands {
         verbose: bool,
     },
 
-    /// Remove stale projects (where directory no longer exists)
+    /// Remove stale projects (where directory no longer exists) and unused snapshot images
     #[command(alias = "prune")]
     Gc {
         /// Remove without confirmation
@@ -111,11 +256,39 @@ enum Commands {
     /// Show detailed info about current project
     ProjectInfo,
 
+    /// Show how a container's frozen config differs from its current profile
+    Drift,
+
     /// Manage volume mounts for a container
     Mount {
         #[command(subcommand)]
         action: MountAction,
     },
+
+    /// Manage published ports for a container
+    Port {
+        #[command(subcommand)]
+        action: PortAction,
+    },
+
+    /// Manage profiles
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+
+    /// Print a shell completion script (e.g. `source <(claudepod completions bash)`)
+    Completions {
+        /// Shell to generate the script for
+        shell: CompletionShell,
+    },
+
+    /// List dynamic completion candidates (used by the completion scripts)
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        /// What to list
+        kind: CandidateKind,
+    },
 }
 
 #[derive(Subcommand)]
@@ -140,6 +313,94 @@ enum MountAction {
     },
 }
 
+#[derive(Subcommand)]
+enum SessionAction {
+    /// Undo the changes a session made to the project
+    Revert {
+        /// Session id, as listed by 'claudepod sessions'
+        id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SnapshotsAction {
+    /// List snapshots (the default)
+    List,
+
+    /// Remove a snapshot and its image
+    #[command(alias = "rm")]
+    Remove {
+        /// Snapshot to remove
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfileAction {
+    /// List available profiles
+    List,
+
+    /// Show a profile as resolved TOML (defaults filled in)
+    Show {
+        /// Profile name
+        name: String,
+    },
+
+    /// Create a new profile as a copy of an existing one
+    New {
+        /// Name of the new profile
+        name: String,
+
+        /// Profile to copy
+        #[arg(long, default_value = "default")]
+        from: String,
+    },
+
+    /// Edit a profile in $EDITOR, validating it before saving
+    Edit {
+        /// Profile name
+        #[arg(default_value = "default")]
+        name: String,
+    },
+
+    /// Validate a profile file, including host paths it refers to
+    Validate {
+        /// Path to the profile TOML file
+        file: PathBuf,
+    },
+
+    /// Show field-level differences between two profiles
+    Diff {
+        /// Profile name or TOML file
+        a: String,
+
+        /// Profile name or TOML file
+        b: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum PortAction {
+    /// Publish a port (PORT or HOST_PORT:CONTAINER_PORT, optionally /udp)
+    Add {
+        /// Port spec: PORT or HOST_PORT:CONTAINER_PORT
+        port: String,
+
+        /// Host address to bind (default: 127.0.0.1)
+        #[arg(long)]
+        address: Option<String>,
+    },
+
+    /// List configured ports and the actual host bindings
+    List,
+
+    /// Stop publishing a container port
+    Remove {
+        /// Container port to remove (e.g. 3000 or 5353/udp)
+        port: String,
+    },
+}
+
 fn main() {
     if let Err(e) = run() {
         eprintln!("Error: {}", e);
@@ -160,16 +421,73 @@ fn run() -> Result<()> {
         Some(Commands::Init { profile, force }) => cmd_init(&profile, container_name, force),
         Some(Commands::Reset { all }) => cmd_reset(container_name, all),
         Some(Commands::List) => cmd_list(),
-        Some(Commands::Save { output }) => cmd_save(container_name, output),
+        Some(Commands::Save { output, compress }) => cmd_save(container_name, output, compress),
         Some(Commands::Load { tarfile, profile }) => cmd_load(&tarfile, &profile, container_name),
+        Some(Commands::Push {
+            host,
+            dir,
+            compress,
+            remote_claudepod,
+        }) => cmd_push(
+            &host,
+            dir.as_deref(),
+            compress,
+            &remote_claudepod,
+            container_name,
+        ),
+        Some(Commands::Pull {
+            host,
+            dir,
+            compress,
+            remote_claudepod,
+        }) => cmd_pull(
+            &host,
+            dir.as_deref(),
+            compress,
+            &remote_claudepod,
+            container_name,
+        ),
+        Some(Commands::Snapshot { name, message }) => {
+            cmd_snapshot(container_name, name, message.as_deref())
+        }
+        Some(Commands::Restore { name }) => cmd_restore(container_name, &name),
+        Some(Commands::Rollback { sessions }) => cmd_rollback(container_name, sessions),
+        Some(Commands::Sessions) => cmd_sessions(),
+        Some(Commands::Session { action }) => cmd_session(action),
+        Some(Commands::Snapshots { action }) => {
+            cmd_snapshots(container_name, action.unwrap_or(SnapshotsAction::List))
+        }
+        Some(Commands::Diff { stat }) => cmd_diff(container_name, stat),
+        Some(Commands::Apply { paths, force }) => cmd_apply(container_name, &paths, force),
+        Some(Commands::Discard { paths }) => cmd_discard(container_name, &paths),
         Some(Commands::Projects { verbose }) => cmd_projects(verbose),
         Some(Commands::Gc { force }) => cmd_gc(force),
         Some(Commands::Unlink { remove_containers }) => cmd_unlink(remove_containers),
         Some(Commands::ProjectInfo) => cmd_project_info(),
+        Some(Commands::Drift) => cmd_drift(container_name),
         Some(Commands::Mount { action }) => cmd_mount(container_name, action),
-        Some(Commands::Run { command, args }) => {
-            let cmd_name = command.unwrap_or_else(|| "claude".to_string());
-            cmd_run(container_name, &cmd_name, args)
+        Some(Commands::Port { action }) => cmd_port(container_name, action),
+        Some(Commands::Profile { action }) => cmd_profile(action),
+        Some(Commands::Completions { shell }) => {
+            print!("{}", completions::script(shell, &mut Cli::command()));
+            Ok(())
+        }
+        Some(Commands::Complete { kind }) => cmd_complete(container_name, kind),
+        Some(Commands::Run { env, command, args }) => {
+            cmd_run(container_name, command.as_deref(), args, &env)
+        }
+        Some(Commands::Exec {
+            user,
+            root,
+            workdir,
+            env,
+            program,
+            args,
+        }) => {
+            let user = if root { Some("root".to_string()) } else { user };
+            let mut argv = vec![program];
+            argv.extend(args);
+            cmd_exec(container_name, argv, &env, user, workdir)
         }
         None => {
             // Default behavior: run default command with all args
@@ -302,8 +620,8 @@ fn cmd_init(profile_name: &str, container_name_arg: Option<&str>, force: bool) -
         // Remove existing container
         let docker_name = container_name(&existing.uuid);
         println!("Removing existing container: {}", docker_name);
-        let old_profile = Profile::load(&existing.profile).unwrap_or_else(|_| Profile::default());
-        let _ = DockerClient::remove_container(&docker_name, &old_profile.docker.container_runtime);
+        let _ =
+            DockerClient::remove_container(&docker_name, &existing.config.docker.container_runtime);
         data.remove_container(container_name_str);
     }
 
@@ -350,17 +668,20 @@ fn cmd_init(profile_name: &str, container_name_arg: Option<&str>, force: bool) -
     let uuid = generate_uuid();
     let docker_name = container_name(&uuid);
     println!("Creating container: {} ({})", container_name_str, docker_name);
-    DockerClient::create_container(&profile.docker, &image_tag, &project_dir, &docker_name)?;
+    let tree = prepare_workspace(&project_id, container_name_str, &profile, &project_dir)?;
+    let mut spec = ContainerSpec::from_profile(&profile);
+    spec.workspace = tree.as_deref();
+    DockerClient::create_container(&spec, &image_tag, &project_dir, &docker_name)?;
+
+    // Setup hooks run now, while the project is being initialized
+    if !profile.hooks.on_create.is_empty() {
+        let proxy = DockerClient::start_egress_proxy(&profile.network, runtime, &docker_name)?;
+        let proxy_env = proxy.as_ref().map(EgressProxy::env).unwrap_or_default();
+        DockerClient::start_created(&spec, &docker_name, &project_dir, &proxy_env)?;
+    }
 
     // 10. Update project data with frozen configuration
-    let info = ContainerInfo {
-        uuid,
-        profile: profile_name.to_string(),
-        created_at: Utc::now(),
-        image_tag: image_tag.clone(),
-        docker: Some(profile.docker.clone()),
-        commands: Some(profile.cmd.clone()),
-    };
+    let info = ContainerInfo::new(uuid, profile_name, image_tag, profile)?;
     data.add_container(container_name_str, info);
 
     // Set as default if it's the first container or if it's named "main"
@@ -378,92 +699,251 @@ fn cmd_init(profile_name: &str, container_name_arg: Option<&str>, force: bool) -
     Ok(())
 }
 
-fn cmd_run(container_name_arg: Option<&str>, command_name: &str, args: Vec<String>) -> Result<()> {
-    // 1. Load index and find/create project
-    let mut index = ProjectsIndex::load()?;
-    let (_project_id, project_dir, data) = ensure_project_exists(&mut index)?;
-    index.save()?;
+/// Copy the project into the container's overlay workspace if it isn't yet.
+/// Returns the copy to mount, or `None` in bind mode.
+fn prepare_workspace(
+    project_id: &str,
+    name: &str,
+    profile: &Profile,
+    project_dir: &Path,
+) -> Result<Option<PathBuf>> {
+    if profile.docker.workspace_mode != "overlay" {
+        return Ok(None);
+    }
+    let workspace = Workspace::new(paths::workspace_dir(project_id, name));
+    workspace.ensure(project_dir)?;
+    Ok(Some(workspace.tree()))
+}
 
-    // 2. Get container info
-    let (name, info) = data.get_container(container_name_arg)?;
+/// A project's container and the frozen config needed to run things in it
+struct RunContext {
+    project_id: String,
+    name: String,
+    info: ContainerInfo,
+    project_dir: PathBuf,
+}
 
-    // 3. Get docker config and commands (use stored config or fallback to profile)
-    let (docker_config, commands_config, image_tag) = match (&info.docker, &info.commands) {
-        (Some(docker), Some(commands)) => {
-            // Use stored configuration (frozen at creation time)
-            let tag = if info.image_tag.is_empty() {
-                // Backwards compatibility: compute from profile if not stored
-                let profile = Profile::load(&info.profile)?;
-                let hash = profile.compute_hash()?;
-                format!("claudepod:{}", &hash[..12])
-            } else {
-                info.image_tag.clone()
-            };
-            (docker.clone(), commands.clone(), tag)
-        }
-        _ => {
-            // Backwards compatibility: load from profile
-            let profile = Profile::load(&info.profile).map_err(|_| {
-                ClaudepodError::ProfileNotFound(format!(
-                    "Profile '{}' not found. The profile used to create this container may have been deleted.",
-                    info.profile
-                ))
-            })?;
-            let hash = profile.compute_hash()?;
-            let tag = format!("claudepod:{}", &hash[..12]);
-            (profile.docker.clone(), profile.cmd.clone(), tag)
+impl RunContext {
+    /// Load the container for the current project
+    fn load(container_name_arg: Option<&str>) -> Result<Self> {
+        // 1. Load index and find/create project
+        let mut index = ProjectsIndex::load()?;
+        let (project_id, project_dir, data) = ensure_project_exists(&mut index)?;
+        index.save()?;
+
+        // 2. Get container info (with its frozen config)
+        let (name, info) = data.get_container(container_name_arg)?;
+
+        Ok(Self {
+            project_id,
+            name: name.to_string(),
+            info: info.clone(),
+            project_dir,
+        })
+    }
+
+    /// Resolve exec-time environment (env_file, host passthrough, -e overrides)
+    fn env(&self, env_overrides: &[String]) -> Result<Vec<(String, String)>> {
+        self.info
+            .config
+            .exec_env
+            .resolve(&self.project_dir, env_overrides, |key| {
+                std::env::var(key).ok()
+            })
+    }
+
+    /// Resolve a command (declared in [cmd] or a raw program) into exec options.
+    /// Precedence for variables: exec_env, then the command's env, then `-e`.
+    fn command_exec(
+        &self,
+        command_name: &str,
+        args: &[String],
+        env_overrides: &[String],
+    ) -> Result<ExecOptions> {
+        let resolved = self.info.config.cmd.resolve_command(command_name, args)?;
+
+        let mut overrides: Vec<String> = resolved
+            .env
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        overrides.extend(env_overrides.iter().cloned());
+
+        // Working directory: the command's (relative to the project root),
+        // otherwise the current directory (may be subdirectory of project)
+        let working_dir = match resolved.workdir {
+            Some(ref dir) => self.project_dir.join(dir),
+            None => std::env::current_dir()?,
+        };
+
+        Ok(ExecOptions {
+            argv: resolved.argv,
+            env: self.env(&overrides)?,
+            working_dir,
+            user: resolved.user,
+            pre: resolved.pre,
+            post: resolved.post,
+        })
+    }
+
+    /// Execute a command. A run of the default command is an agent session,
+    /// wrapped in the checkpoints set in the profile's [session] section.
+    fn run(&self, command_name: &str, exec: &ExecOptions) -> Result<()> {
+        if command_name != self.info.config.cmd.default {
+            return self.exec(exec);
         }
-    };
 
-    // 4. Get docker container name
-    let docker_name = container_name(&info.uuid);
+        self.auto_snapshot()?;
 
-    // 5. Get current working directory (may be subdirectory of project)
-    let current_dir = std::env::current_dir()?;
+        let git_session = if self.info.config.session.git_checkpoint {
+            if sessions::is_repo(&self.project_dir) {
+                Some(sessions::begin(&self.project_dir)?)
+            } else {
+                eprintln!(
+                    "Warning: git_checkpoint is set, but the project is not a git repository"
+                );
+                None
+            }
+        } else {
+            None
+        };
 
-    println!("Using container '{}' ({})", name, docker_name);
-
-    // 6. Run command in container
-    DockerClient::run(
-        &docker_config,
-        &commands_config,
-        &docker_name,
-        &image_tag,
-        command_name,
-        &args,
-        &project_dir,
-        &current_dir,
-    )
-}
+        let result = self.exec(exec);
+
+        if let Some(id) = git_session {
+            match sessions::finish(&self.project_dir, &id) {
+                Ok(()) => println!(
+                    "Session {} checkpointed. Run 'claudepod session revert {}' to undo it.",
+                    id, id
+                ),
+                Err(e) => eprintln!(
+                    "Warning: could not checkpoint the end of session {}: {}",
+                    id, e
+                ),
+            }
+        }
 
-fn cmd_run_with_args(container_name_arg: Option<&str>, args: Vec<String>) -> Result<()> {
-    if args.is_empty() {
-        return cmd_run(container_name_arg, "claude", vec![]);
+        result
     }
 
-    // Check if first arg is a known command name
-    let mut index = ProjectsIndex::load()?;
-    if let Ok((project_id, _, _)) = find_project(&mut index).map(|(id, path)| {
-        let data = load_project_data(&id).unwrap_or_default();
-        (id, path, data)
-    }) {
-        if let Ok(data) = load_project_data(&project_id) {
-            if let Ok((_, info)) = data.get_container(container_name_arg) {
-                if let Ok(profile) = Profile::load(&info.profile) {
-                    if let Some(first_arg) = args.first() {
-                        if profile.cmd.commands.contains_key(first_arg.as_str()) {
-                            let command_name = first_arg.clone();
-                            let remaining_args = args[1..].to_vec();
-                            return cmd_run(container_name_arg, &command_name, remaining_args);
-                        }
-                    }
-                }
+    /// Snapshot the container (if `session.auto_snapshot` is set) and rotate
+    /// out the oldest automatic snapshots
+    fn auto_snapshot(&self) -> Result<()> {
+        let Some(auto) = &self.info.config.session.auto_snapshot else {
+            return Ok(());
+        };
+        let docker_name = container_name(&self.info.uuid);
+        let runtime = &self.info.config.docker.container_runtime;
+
+        // A container that doesn't exist yet has no state to go back to
+        if !DockerClient::container_exists(&docker_name, runtime) {
+            return Ok(());
+        }
+
+        let mut data = load_project_data(&self.project_id)?;
+        let stamp = Utc::now().format("%Y%m%d-%H%M%S").to_string();
+        let mut snapshot = format!("auto-{}", stamp);
+        for n in 2.. {
+            if data.snapshot_owner(&snapshot).is_none() {
+                break;
             }
+            snapshot = format!("auto-{}-{}", stamp, n);
         }
+
+        let info = data.get_container_mut(Some(&self.name))?;
+        take_snapshot(
+            &self.project_id,
+            info,
+            &snapshot,
+            &format!("Before '{}' session", self.info.config.cmd.default),
+            true,
+        )?;
+
+        let autos: Vec<Snapshot> = info.snapshots.iter().filter(|s| s.auto).cloned().collect();
+        let expired = &autos[..autos.len().saturating_sub(auto.keep)];
+        for old in expired {
+            // A leftover image is removed by gc later
+            if let Err(e) = DockerClient::remove_image(&old.image_tag, runtime) {
+                eprintln!("Warning: could not remove snapshot '{}': {}", old.name, e);
+            }
+        }
+        info.snapshots.retain(|s| !expired.contains(s));
+
+        save_project_data(&self.project_id, &data)
     }
 
-    // Default command with all args
-    cmd_run(container_name_arg, "claude", args)
+    /// Create/start the container as needed and execute in it
+    fn exec(&self, exec: &ExecOptions) -> Result<()> {
+        let docker_name = container_name(&self.info.uuid);
+        println!("Using container '{}' ({})", self.name, docker_name);
+
+        let tree = prepare_workspace(
+            &self.project_id,
+            &self.name,
+            &self.info.config,
+            &self.project_dir,
+        )?;
+        let mut spec = ContainerSpec::from_profile(&self.info.config);
+        spec.workspace = tree.as_deref();
+        DockerClient::run(
+            &spec,
+            &docker_name,
+            &self.info.image_tag,
+            exec,
+            &self.project_dir,
+        )
+    }
+}
+
+fn cmd_run(
+    container_name_arg: Option<&str>,
+    command_name: Option<&str>,
+    args: Vec<String>,
+    env_overrides: &[String],
+) -> Result<()> {
+    let ctx = RunContext::load(container_name_arg)?;
+    let command_name = command_name.unwrap_or(&ctx.info.config.cmd.default);
+
+    // Declared [cmd] entries are resolved, anything else runs as a raw program
+    let exec = ctx.command_exec(command_name, &args, env_overrides)?;
+    ctx.run(command_name, &exec)
+}
+
+fn cmd_exec(
+    container_name_arg: Option<&str>,
+    argv: Vec<String>,
+    env_overrides: &[String],
+    user: Option<String>,
+    workdir: Option<PathBuf>,
+) -> Result<()> {
+    let ctx = RunContext::load(container_name_arg)?;
+
+    let current_dir = std::env::current_dir()?;
+    let working_dir = match workdir {
+        Some(dir) => current_dir.join(dir),
+        None => current_dir,
+    };
+
+    let exec = ExecOptions {
+        argv,
+        env: ctx.env(env_overrides)?,
+        working_dir,
+        user,
+        pre: None,
+        post: None,
+    };
+    ctx.exec(&exec)
+}
+
+fn cmd_run_with_args(container_name_arg: Option<&str>, args: Vec<String>) -> Result<()> {
+    let ctx = RunContext::load(container_name_arg)?;
+
+    // A leading declared command runs with the rest, otherwise everything
+    // goes to the default command (both from the container's frozen config)
+    let (command_name, args) = ctx.info.config.cmd.dispatch(&args);
+
+    let exec = ctx.command_exec(&command_name, &args, &[])?;
+    ctx.run(&command_name, &exec)
 }
 
 fn cmd_reset(container_name_arg: Option<&str>, all: bool) -> Result<()> {
@@ -486,8 +966,7 @@ fn cmd_reset(container_name_arg: Option<&str>, all: bool) -> Result<()> {
 
         for (name, info) in containers {
             let docker_name = container_name(&info.uuid);
-            let profile = Profile::load(&info.profile).unwrap_or_else(|_| Profile::default());
-            let runtime = &profile.docker.container_runtime;
+            let runtime = &info.config.docker.container_runtime;
 
             if DockerClient::container_exists(&docker_name, runtime) {
                 println!("Removing container '{}' ({})...", name, docker_name);
@@ -509,8 +988,7 @@ fn cmd_reset(container_name_arg: Option<&str>, all: bool) -> Result<()> {
         let info = info.clone();
 
         let docker_name = container_name(&info.uuid);
-        let profile = Profile::load(&info.profile).unwrap_or_else(|_| Profile::default());
-        let runtime = &profile.docker.container_runtime;
+        let runtime = &info.config.docker.container_runtime;
 
         if DockerClient::container_exists(&docker_name, runtime) {
             println!("Removing container '{}' ({})...", name, docker_name);
@@ -525,6 +1003,13 @@ fn cmd_reset(container_name_arg: Option<&str>, all: bool) -> Result<()> {
 
         data.remove_container(&name);
 
+        // Unapplied changes in an overlay workspace go with the container
+        let workspace_dir = paths::workspace_dir(&project_id, &name);
+        if workspace_dir.exists() {
+            println!("Removing overlay workspace...");
+            fs::remove_dir_all(&workspace_dir)?;
+        }
+
         if data.containers.is_empty() {
             // No containers left, remove project from index
             index.remove(&project_id);
@@ -598,97 +1083,138 @@ fn cmd_list() -> Result<()> {
     Ok(())
 }
 
-fn cmd_save(container_name_arg: Option<&str>, output: Option<String>) -> Result<()> {
-    use std::process::Command;
-
-    // 1. Load index and find project
+fn cmd_save(
+    container_name_arg: Option<&str>,
+    output: Option<String>,
+    compress: Option<Compression>,
+) -> Result<()> {
+    // 1. Load index and find project. When streaming, stdout carries the
+    // bundle, so there is no prompt to initialize a project.
+    let to_stdout = output.as_deref() == Some("-");
     let mut index = ProjectsIndex::load()?;
-    let (_, _, data) = ensure_project_exists(&mut index)?;
+    let data = if to_stdout {
+        let (project_id, _) = find_project(&mut index)?;
+        load_project_data(&project_id)?
+    } else {
+        ensure_project_exists(&mut index)?.2
+    };
     index.save()?;
 
     // 2. Get container info
     let (name, info) = data.get_container(container_name_arg)?;
 
-    // 3. Get runtime from stored config or fallback to profile
-    let runtime = info
-        .docker
-        .as_ref()
-        .map(|d| d.container_runtime.clone())
-        .unwrap_or_else(|| {
-            Profile::load(&info.profile)
-                .map(|p| p.docker.container_runtime.clone())
-                .unwrap_or_else(|_| "podman".to_string())
-        });
-
-    // 4. Get docker container name
-    let docker_name = container_name(&info.uuid);
-
-    // 5. Check container exists
-    if !DockerClient::container_exists(&docker_name, &runtime) {
-        return Err(ClaudepodError::Docker(format!(
-            "Container '{}' ({}) does not exist. Run 'claudepod init' first.",
-            name, docker_name
-        )));
+    // 3. Stream to stdout (uncompressed unless asked)
+    if to_stdout {
+        if io::stdout().is_terminal() {
+            return Err(ClaudepodError::Other(
+                "Refusing to write a bundle to a terminal; redirect or pipe the output".to_string(),
+            ));
+        }
+        let compression = compress.unwrap_or(Compression::None);
+        let mut out = write_save_bundle(name, info, io::stdout().lock(), compression)?;
+        out.flush()?;
+        return Ok(());
     }
 
-    // 6. Determine output path
+    // 4. Determine output path and compression (from the file name by default)
+    let compression = compress.unwrap_or_else(|| {
+        output
+            .as_deref()
+            .map(|path| Compression::from_path(Path::new(path)))
+            .unwrap_or(Compression::None)
+    });
     let output_path = match output {
         Some(path) => PathBuf::from(path),
-        None => PathBuf::from(format!("{}.tar", docker_name)),
+        None => PathBuf::from(format!(
+            "{}.{}",
+            container_name(&info.uuid),
+            compression.extension()
+        )),
     };
 
-    // 7. Export container
-    println!(
-        "Exporting container '{}' ({}) to '{}'...",
-        name,
-        docker_name,
-        output_path.display()
-    );
-    DockerClient::export_container(&docker_name, &output_path, &runtime)?;
-
-    // 8. Append config to tar file
-    let config_toml = toml::to_string_pretty(info)?;
-    let temp_dir = std::env::temp_dir();
-    let config_path = temp_dir.join(".claudepod-config.toml");
-    fs::write(&config_path, &config_toml)?;
-
-    // Use tar to append the config file
-    let status = Command::new("tar")
-        .args([
-            "--append",
-            "-f",
-            &output_path.to_string_lossy(),
-            "-C",
-            &temp_dir.to_string_lossy(),
-            ".claudepod-config.toml",
-        ])
-        .status()
-        .map_err(|e| ClaudepodError::Other(format!("Failed to append config to tar: {}", e)))?;
-
-    // Clean up temp file
-    let _ = fs::remove_file(&config_path);
-
-    if !status.success() {
-        return Err(ClaudepodError::Other(
-            "Failed to append config to tar archive".to_string(),
-        ));
+    // 5. Write the bundle
+    let written = File::create(&output_path)
+        .map_err(ClaudepodError::from)
+        .and_then(|file| {
+            println!("Writing bundle to '{}'...", output_path.display());
+            write_save_bundle(name, info, BufWriter::new(file), compression)
+        })
+        .and_then(|mut out| Ok(out.flush()?));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&output_path);
+        return Err(e);
     }
 
-    // 9. Show file size
+    // 6. Show file size
     if let Ok(metadata) = fs::metadata(&output_path) {
         let size_mb = metadata.len() as f64 / (1024.0 * 1024.0);
-        println!("Export complete: {:.1} MB (config included)", size_mb);
+        println!("Save complete: {:.1} MB (config included)", size_mb);
     } else {
-        println!("Export complete.");
+        println!("Save complete.");
     }
 
     Ok(())
 }
 
+/// Export a container and write it as a bundle to `out`. Progress goes to
+/// stderr, as `out` may be stdout. The export streams straight into the
+/// bundle, nothing is staged on disk.
+fn write_save_bundle<W: Write>(
+    name: &str,
+    info: &ContainerInfo,
+    out: W,
+    compression: Compression,
+) -> Result<W> {
+    let runtime = &info.config.docker.container_runtime;
+    let docker_name = container_name(&info.uuid);
+
+    if !DockerClient::container_exists(&docker_name, runtime) {
+        return Err(ClaudepodError::Docker(format!(
+            "Container '{}' ({}) does not exist. Run 'claudepod init' first.",
+            name, docker_name
+        )));
+    }
+
+    // Image user/env/entrypoint, which the export drops
+    let image_config = match DockerClient::image_config(&docker_name, runtime) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            eprintln!(
+                "Warning: could not read the image config ({}); load will use the profile's",
+                e
+            );
+            None
+        }
+    };
+
+    // Render the image recipe, then export the filesystem
+    let (dockerfile, entrypoint) = Generator::new()?.render(&info.config)?;
+    let members: [(&str, &[u8]); 2] = [
+        ("Dockerfile", dockerfile.as_bytes()),
+        ("entrypoint.sh", entrypoint.as_bytes()),
+    ];
+
+    eprintln!("Exporting container '{}' ({})...", name, docker_name);
+    let rootfs = DockerClient::export_container(&docker_name, runtime)?;
+    bundle::write(
+        out,
+        compression,
+        Manifest::new(info.clone(), image_config),
+        &members,
+        rootfs,
+    )
+}
+
 fn cmd_load(tarfile: &str, profile_name: &str, container_name_arg: Option<&str>) -> Result<()> {
-    use std::process::Command;
+    if tarfile == "-" {
+        if io::stdin().is_terminal() {
+            return Err(ClaudepodError::Other(
+                "Refusing to read a bundle from a terminal; redirect or pipe the input".to_string(),
+            ));
+        }
+        return load_bundle(io::stdin().lock(), None, profile_name, container_name_arg);
+    }
 
-    let container_name_str = container_name_arg.unwrap_or("main");
     let tarfile_path = PathBuf::from(tarfile);
 
     // 1. Verify tar file exists
@@ -699,33 +1225,55 @@ fn cmd_load(tarfile: &str, profile_name: &str, container_name_arg: Option<&str>)
         )));
     }
 
-    // 2. Try to extract config from tar
-    let temp_dir = std::env::temp_dir();
-    let config_path = temp_dir.join(".claudepod-config.toml");
-
-    // Clean up any existing temp config
-    let _ = fs::remove_file(&config_path);
-
-    let extract_result = Command::new("tar")
-        .args([
-            "-xf",
-            tarfile,
-            "-C",
-            &temp_dir.to_string_lossy(),
-            ".claudepod-config.toml",
-        ])
-        .output();
-
-    let saved_config: Option<ContainerInfo> = if let Ok(output) = extract_result {
-        if output.status.success() && config_path.exists() {
-            let content = fs::read_to_string(&config_path)?;
-            let _ = fs::remove_file(&config_path);
-            toml::from_str(&content).ok()
-        } else {
-            None
+    load_bundle(
+        File::open(&tarfile_path)?,
+        Some(&tarfile_path),
+        profile_name,
+        container_name_arg,
+    )
+}
+
+/// Load a container from a bundle read from `reader`. `file` is the path it
+/// was opened from, which is needed to fall back to a plain export; streams
+/// must be bundles.
+fn load_bundle<R: Read>(
+    reader: R,
+    file: Option<&Path>,
+    profile_name: &str,
+    container_name_arg: Option<&str>,
+) -> Result<()> {
+    let container_name_str = container_name_arg.unwrap_or("main");
+
+    // 2. Import the bundle's filesystem as it is read; checksums are
+    // verified before the import sees the end of it. Plain exports from
+    // older versions are imported as-is, with the config appended to them
+    // if any.
+    let loaded = bundle::read(reader, |manifest, rootfs| {
+        println!(
+            "Bundle saved by claudepod {} on {}",
+            manifest.claudepod_version,
+            manifest.created_at.format("%Y-%m-%d %H:%M:%S")
+        );
+        let info = loaded_container_info(Some(manifest.container), profile_name)?;
+        import_rootfs(rootfs, &info, manifest.image_config)?;
+        Ok(info)
+    })?;
+    let info = match loaded {
+        Some(info) => info,
+        None => {
+            let tarfile_path = file.ok_or_else(|| {
+                ClaudepodError::Other(
+                    "Input is not a claudepod bundle (plain exports can only be loaded from a file)"
+                        .to_string(),
+                )
+            })?;
+            let config = bundle::legacy_config(tarfile_path)?.and_then(|content| {
+                migrations::parse(&content, migrations::CONTAINER_MIGRATIONS).ok()
+            });
+            let info = loaded_container_info(config, profile_name)?;
+            import_rootfs(&mut File::open(tarfile_path)?, &info, None)?;
+            info
         }
-    } else {
-        None
     };
 
     // 3. Get current directory for project
@@ -736,64 +1284,19 @@ fn cmd_load(tarfile: &str, profile_name: &str, container_name_arg: Option<&str>)
     let (project_id, project_dir) = get_or_create_project(&mut index, &current_dir)?;
     let mut data = load_project_data(&project_id)?;
 
-    // 5. Determine config to use
-    let (docker_config, commands_config, image_tag) = if let Some(ref config) = saved_config {
-        println!("Found saved configuration in tar file");
-        let docker = config.docker.clone().unwrap_or_else(|| {
-            Profile::load(profile_name)
-                .map(|p| p.docker.clone())
-                .unwrap_or_default()
-        });
-        let commands = config.commands.clone().unwrap_or_else(|| {
-            Profile::load(profile_name)
-                .map(|p| p.cmd.clone())
-                .unwrap_or_default()
-        });
-        let tag = if config.image_tag.is_empty() {
-            format!("claudepod:imported-{}", &generate_uuid()[..8])
-        } else {
-            config.image_tag.clone()
-        };
-        (docker, commands, tag)
-    } else {
-        println!(
-            "No saved configuration found, using profile '{}'",
-            profile_name
-        );
-        Profile::ensure_default()?;
-        let profile = Profile::load(profile_name)?;
-        let tag = format!("claudepod:imported-{}", &generate_uuid()[..8]);
-        (profile.docker.clone(), profile.cmd.clone(), tag)
-    };
-
-    let runtime = &docker_config.container_runtime;
-
-    // 6. Import tar file as image
-    println!("Importing container image...");
-    DockerClient::import_image(&tarfile_path, &image_tag, runtime)?;
-
-    // 7. Generate UUID and create container
-    let uuid = generate_uuid();
-    let docker_name = container_name(&uuid);
+    // 5. Create container
+    let docker_name = container_name(&info.uuid);
 
     println!(
         "Creating container: {} ({})",
         container_name_str, docker_name
     );
-    DockerClient::create_container(&docker_config, &image_tag, &project_dir, &docker_name)?;
-
-    // 8. Update project data
-    let info = ContainerInfo {
-        uuid,
-        profile: saved_config
-            .as_ref()
-            .map(|c| c.profile.clone())
-            .unwrap_or_else(|| profile_name.to_string()),
-        created_at: Utc::now(),
-        image_tag,
-        docker: Some(docker_config),
-        commands: Some(commands_config),
-    };
+    let tree = prepare_workspace(&project_id, container_name_str, &info.config, &project_dir)?;
+    let mut spec = ContainerSpec::from_profile(&info.config);
+    spec.workspace = tree.as_deref();
+    DockerClient::create_container(&spec, &info.image_tag, &project_dir, &docker_name)?;
+
+    // 6. Update project data
     data.add_container(container_name_str, info);
 
     // Set as default if it's the first container or if it's named "main"
@@ -810,13 +1313,157 @@ fn cmd_load(tarfile: &str, profile_name: &str, container_name_arg: Option<&str>)
     Ok(())
 }
 
-fn cmd_projects(verbose: bool) -> Result<()> {
-    let index = ProjectsIndex::load()?;
-
-    if index.projects.is_empty() {
-        println!("No tracked projects.");
-        println!("\nRun 'claudepod init' in a project directory to start tracking.");
-        return Ok(());
+/// Container record for a loaded bundle: its frozen config (older layouts
+/// are migrated when parsed), or the given profile if it has none
+fn loaded_container_info(
+    saved: Option<ContainerInfo>,
+    profile_name: &str,
+) -> Result<ContainerInfo> {
+    let uuid = generate_uuid();
+    match saved {
+        Some(saved) => {
+            println!("Found saved configuration");
+            let image_tag = if saved.image_tag.is_empty() {
+                format!("claudepod:imported-{}", &generate_uuid()[..8])
+            } else {
+                saved.image_tag.clone()
+            };
+            // Snapshot images stay on the machine that took them
+            Ok(ContainerInfo {
+                uuid,
+                created_at: Utc::now(),
+                image_tag,
+                snapshots: Vec::new(),
+                ..saved
+            })
+        }
+        None => {
+            println!(
+                "No saved configuration found, using profile '{}'",
+                profile_name
+            );
+            Profile::ensure_default()?;
+            let profile = Profile::load(profile_name)?;
+            let tag = format!("claudepod:imported-{}", &generate_uuid()[..8]);
+            ContainerInfo::new(uuid, profile_name, tag, profile)
+        }
+    }
+}
+
+/// Import a filesystem export as the container's image, restoring the image
+/// config (user, env, entrypoint) that the export dropped
+fn import_rootfs<R: Read + ?Sized>(
+    rootfs: &mut R,
+    info: &ContainerInfo,
+    image_config: Option<ImageConfig>,
+) -> Result<()> {
+    let image_config = image_config.unwrap_or_else(|| ImageConfig::from_profile(&info.config));
+    println!("Importing container image...");
+    DockerClient::import_image(
+        rootfs,
+        &info.image_tag,
+        &info.config.docker.container_runtime,
+        &image_config.changes(),
+    )
+}
+
+/// Project directory to mirror on the remote host: the current project's, or
+/// the current directory if there is none
+fn local_project_dir(index: &ProjectsIndex) -> Result<PathBuf> {
+    let current_dir = std::env::current_dir()?;
+    Ok(index
+        .find_project_for_path(&current_dir)
+        .map(|(_, entry)| PathBuf::from(entry.path))
+        .unwrap_or(current_dir))
+}
+
+fn cmd_push(
+    host: &str,
+    dir: Option<&str>,
+    compression: Compression,
+    remote_claudepod: &str,
+    container_name_arg: Option<&str>,
+) -> Result<()> {
+    let mut index = ProjectsIndex::load()?;
+    let (_, project_dir, data) = ensure_project_exists(&mut index)?;
+    index.save()?;
+    let (name, info) = data.get_container(container_name_arg)?;
+
+    let remote_dir = dir.map_or_else(|| remote::default_dir(&project_dir), str::to_string);
+    let command = remote::command_line(&remote_dir, remote_claudepod, &["-c", name, "load", "-"]);
+
+    println!("Pushing container '{}' to {}:{}...", name, host, remote_dir);
+    let mut child = remote::ssh(host, &command)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| ClaudepodError::Other(format!("Failed to run ssh: {}", e)))?;
+
+    // Dropping the writer closes the pipe, so the remote load sees the end
+    let stdin = child.stdin.take().expect("stdin is piped");
+    let written = write_save_bundle(name, info, BufWriter::new(stdin), compression)
+        .and_then(|mut out| Ok(out.flush()?));
+    let status = child.wait()?;
+
+    // A local failure is what made the remote load fail
+    written?;
+    if !status.success() {
+        return Err(ClaudepodError::Other(format!(
+            "Remote load on '{}' failed ({})",
+            host, status
+        )));
+    }
+
+    println!("Push complete.");
+    Ok(())
+}
+
+fn cmd_pull(
+    host: &str,
+    dir: Option<&str>,
+    compression: Compression,
+    remote_claudepod: &str,
+    container_name_arg: Option<&str>,
+) -> Result<()> {
+    let index = ProjectsIndex::load()?;
+    let remote_dir = match dir {
+        Some(dir) => dir.to_string(),
+        None => remote::default_dir(&local_project_dir(&index)?),
+    };
+
+    let mut args = Vec::new();
+    if let Some(name) = container_name_arg {
+        args.extend(["-c", name]);
+    }
+    args.extend(["save", "-", "--compress", compression.name()]);
+    let command = remote::command_line(&remote_dir, remote_claudepod, &args);
+
+    println!("Pulling container from {}:{}...", host, remote_dir);
+    let mut child = remote::ssh(host, &command)
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| ClaudepodError::Other(format!("Failed to run ssh: {}", e)))?;
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let loaded = load_bundle(stdout, None, "default", container_name_arg);
+    let status = child.wait()?;
+
+    // The remote error (on stderr) explains a truncated or empty stream
+    if loaded.is_err() && !status.success() {
+        return Err(ClaudepodError::Other(format!(
+            "Remote save on '{}' failed ({})",
+            host, status
+        )));
+    }
+    loaded
+}
+
+fn cmd_projects(verbose: bool) -> Result<()> {
+    let index = ProjectsIndex::load()?;
+
+    if index.projects.is_empty() {
+        println!("No tracked projects.");
+        println!("\nRun 'claudepod init' in a project directory to start tracking.");
+        return Ok(());
     }
 
     println!("Tracked projects:\n");
@@ -859,17 +1506,64 @@ fn cmd_projects(verbose: bool) -> Result<()> {
     Ok(())
 }
 
+/// Snapshot images and images left by `restore` (runtime, tag) that no
+/// container of a live project refers to: those of stale projects, removed
+/// containers, superseded restores or lost records. Fails if any project's
+/// data can't be read, as its snapshots would look unused.
+fn unused_snapshots(
+    index: &ProjectsIndex,
+    stale: &[(String, ProjectEntry)],
+) -> Result<Vec<(String, String)>> {
+    let mut runtimes = std::collections::BTreeSet::new();
+    let mut used = std::collections::HashSet::new();
+    for (id, entry) in &index.projects {
+        let data = load_project_data(id).map_err(|e| {
+            ClaudepodError::Other(format!(
+                "Could not read the data of project '{}' ({}): {}\n\
+                 Not removing anything, as its snapshots can't be told apart from unused ones.",
+                entry.name, entry.path, e
+            ))
+        })?;
+        let is_stale = stale.iter().any(|(stale_id, _)| stale_id == id);
+        for info in data.containers.values() {
+            runtimes.insert(info.config.docker.container_runtime.clone());
+            if !is_stale {
+                used.insert(info.image_tag.clone());
+                used.extend(info.snapshots.iter().map(|s| s.image_tag.clone()));
+            }
+        }
+    }
+    if runtimes.is_empty() {
+        runtimes.insert(Profile::default().docker.container_runtime);
+    }
+
+    let mut unused = Vec::new();
+    for runtime in runtimes {
+        for tag in DockerClient::list_image_tags("claudepod", &runtime).unwrap_or_default() {
+            let image_tag = format!("claudepod:{}", tag);
+            let prunable = tag.starts_with("snap-") || tag.starts_with("restore-");
+            if prunable && !used.contains(&image_tag) {
+                unused.push((runtime.clone(), image_tag));
+            }
+        }
+    }
+    Ok(unused)
+}
+
 fn cmd_gc(force: bool) -> Result<()> {
     let mut index = ProjectsIndex::load()?;
 
     let stale = index.find_stale_projects();
+    let snapshots = unused_snapshots(&index, &stale)?;
 
-    if stale.is_empty() {
-        println!("No stale projects found.");
+    if stale.is_empty() && snapshots.is_empty() {
+        println!("No stale projects or unused snapshots found.");
         return Ok(());
     }
 
-    println!("Found {} stale project(s):\n", stale.len());
+    if !stale.is_empty() {
+        println!("Found {} stale project(s):\n", stale.len());
+    }
 
     for (id, entry) in &stale {
         println!("  {} ({})", entry.name, entry.path);
@@ -882,9 +1576,19 @@ fn cmd_gc(force: bool) -> Result<()> {
         }
     }
 
+    if !snapshots.is_empty() {
+        if !stale.is_empty() {
+            println!();
+        }
+        println!("Found {} unused snapshot image(s):\n", snapshots.len());
+        for (_, image_tag) in &snapshots {
+            println!("  {}", image_tag);
+        }
+    }
+
     if !force {
         println!();
-        print!("Remove these projects from tracking? [y/N] ");
+        print!("Remove these projects and snapshots? [y/N] ");
         io::stdout().flush()?;
 
         let mut input = String::new();
@@ -903,11 +1607,7 @@ fn cmd_gc(force: bool) -> Result<()> {
         if let Ok(data) = load_project_data(id) {
             for (name, info) in &data.containers {
                 let docker_name = container_name(&info.uuid);
-                let runtime = info
-                    .docker
-                    .as_ref()
-                    .map(|d| d.container_runtime.clone())
-                    .unwrap_or_else(|| "podman".to_string());
+                let runtime = info.config.docker.container_runtime.clone();
 
                 if DockerClient::container_exists(&docker_name, &runtime) {
                     println!("Removing container '{}' ({})...", name, docker_name);
@@ -927,7 +1627,19 @@ fn cmd_gc(force: bool) -> Result<()> {
 
     index.save()?;
 
-    println!("\nCleaned up {} stale project(s).", stale.len());
+    let mut removed = 0;
+    for (runtime, image_tag) in &snapshots {
+        match DockerClient::remove_image(image_tag, runtime) {
+            Ok(()) => removed += 1,
+            Err(e) => eprintln!("Warning: could not remove {}: {}", image_tag, e),
+        }
+    }
+
+    println!(
+        "\nCleaned up {} stale project(s) and {} snapshot image(s).",
+        stale.len(),
+        removed
+    );
 
     Ok(())
 }
@@ -951,11 +1663,7 @@ fn cmd_unlink(remove_containers: bool) -> Result<()> {
         if let Ok(data) = load_project_data(&project_id) {
             for (name, info) in &data.containers {
                 let docker_name = container_name(&info.uuid);
-                let runtime = info
-                    .docker
-                    .as_ref()
-                    .map(|d| d.container_runtime.clone())
-                    .unwrap_or_else(|| "podman".to_string());
+                let runtime = info.config.docker.container_runtime.clone();
 
                 if DockerClient::container_exists(&docker_name, &runtime) {
                     println!("Removing container '{}' ({})...", name, docker_name);
@@ -1028,11 +1736,7 @@ fn cmd_project_info() -> Result<()> {
             let is_default = name == &data.default;
 
             // Check if container actually exists
-            let runtime = info
-                .docker
-                .as_ref()
-                .map(|d| d.container_runtime.clone())
-                .unwrap_or_else(|| "podman".to_string());
+            let runtime = info.config.docker.container_runtime.clone();
             let exists = DockerClient::container_exists(&docker_name, &runtime);
 
             println!(
@@ -1054,6 +1758,499 @@ fn cmd_project_info() -> Result<()> {
     Ok(())
 }
 
+/// Render a field-level change as `~ path: old -> new`, `- path = old` or `+ path = new`
+fn format_field_diff(field: &FieldDiff) -> String {
+    match (&field.old, &field.new) {
+        (Some(old), Some(new)) => format!("~ {}: {} -> {}", field.path, old, new),
+        (Some(old), None) => format!("- {} = {}", field.path, old),
+        (None, Some(new)) => format!("+ {} = {}", field.path, new),
+        (None, None) => format!("  {}", field.path),
+    }
+}
+
+fn cmd_drift(container_name_arg: Option<&str>) -> Result<()> {
+    let mut index = ProjectsIndex::load()?;
+    let (project_id, _) = find_project(&mut index)?;
+    let data = load_project_data(&project_id)?;
+    let (name, info) = data.get_container(container_name_arg)?;
+
+    let profile = Profile::load(&info.profile).map_err(|_| {
+        ClaudepodError::ProfileNotFound(format!(
+            "Profile '{}' not found. The profile used to create this container may have been deleted.",
+            info.profile
+        ))
+    })?;
+
+    println!("Container '{}' (profile: {})\n", name, info.profile);
+
+    let Drift { rebuild, recreate } = info.drift(&profile)?;
+
+    // Image: out of date only if a field baked into it changed
+    let profile_tag = format!("claudepod:{}", &profile.compute_hash()?[..12]);
+    if info.profile_hash.is_empty() {
+        // Containers migrated from before the hash was recorded
+        println!(
+            "Image: {} (built from an unknown profile version, profile now builds {})",
+            info.image_tag, profile_tag
+        );
+    } else if rebuild.is_empty() {
+        println!("Image: {} (up to date)", info.image_tag);
+    } else {
+        println!(
+            "Image: {} (profile now builds {})",
+            info.image_tag, profile_tag
+        );
+    }
+
+    if !rebuild.is_empty() {
+        println!("\nNeeds an image rebuild:");
+        for field in &rebuild {
+            println!("  {}", format_field_diff(field));
+        }
+    }
+    if !recreate.is_empty() {
+        println!("\nNeeds a container recreate:");
+        for field in &recreate {
+            println!("  {}", format_field_diff(field));
+        }
+    }
+
+    if rebuild.is_empty() && recreate.is_empty() {
+        println!("\nNo drift: the container matches its profile.");
+    } else {
+        println!(
+            "\nRun 'claudepod init {} -c {} --force' to apply the profile (container changes are lost).",
+            info.profile, name
+        );
+    }
+
+    Ok(())
+}
+
+/// If the container exists, commit its state (to preserve filesystem changes)
+/// and remove it, so the next run recreates it from the new image with the
+/// updated frozen config. Returns the new image tag.
+fn commit_for_recreate(docker_name: &str, runtime: &str, prefix: &str) -> Result<Option<String>> {
+    if !DockerClient::container_exists(docker_name, runtime) {
+        return Ok(None);
+    }
+
+    println!("Stopping container...");
+    if DockerClient::container_is_running(docker_name, runtime) {
+        DockerClient::stop_container(docker_name, runtime)?;
+    }
+
+    let new_image_tag = format!(
+        "claudepod:{}-{}",
+        prefix,
+        &generate_uuid().replace('-', "")[..12]
+    );
+    println!("Committing container state to {}...", new_image_tag);
+    DockerClient::commit_container(docker_name, &new_image_tag, runtime)?;
+
+    println!("Removing old container...");
+    DockerClient::remove_container(docker_name, runtime)?;
+
+    Ok(Some(new_image_tag))
+}
+
+/// Commit the container's state to a snapshot image and record it
+fn take_snapshot(
+    project_id: &str,
+    info: &mut ContainerInfo,
+    name: &str,
+    description: &str,
+    auto: bool,
+) -> Result<()> {
+    let docker_name = container_name(&info.uuid);
+    let runtime = &info.config.docker.container_runtime;
+    let image_tag = snapshot_tag(project_id, name);
+
+    println!("Committing container state to {}...", image_tag);
+    DockerClient::commit_container(&docker_name, &image_tag, runtime)?;
+
+    info.snapshots.push(Snapshot {
+        name: name.to_string(),
+        created_at: Utc::now(),
+        description: description.to_string(),
+        image_tag,
+        auto,
+    });
+    Ok(())
+}
+
+fn cmd_snapshot(
+    container_name_arg: Option<&str>,
+    name: Option<String>,
+    message: Option<&str>,
+) -> Result<()> {
+    let mut index = ProjectsIndex::load()?;
+    let (project_id, _project_dir, mut data) = ensure_project_exists(&mut index)?;
+    index.save()?;
+
+    let snapshot = name.unwrap_or_else(|| Utc::now().format("%Y%m%d-%H%M%S").to_string());
+    validate_snapshot_name(&snapshot)?;
+    if let Some(owner) = data.snapshot_owner(&snapshot) {
+        return Err(ClaudepodError::Other(format!(
+            "Snapshot '{}' already exists (container '{}').",
+            snapshot, owner
+        )));
+    }
+
+    let (name, _) = data.get_container(container_name_arg)?;
+    let name = name.clone();
+    let info = data.get_container_mut(Some(&name))?;
+    let docker_name = container_name(&info.uuid);
+    if !DockerClient::container_exists(&docker_name, &info.config.docker.container_runtime) {
+        return Err(ClaudepodError::Docker(format!(
+            "Container '{}' ({}) does not exist. Run 'claudepod init' first.",
+            name, docker_name
+        )));
+    }
+
+    take_snapshot(
+        &project_id,
+        info,
+        &snapshot,
+        message.unwrap_or_default(),
+        false,
+    )?;
+    save_project_data(&project_id, &data)?;
+
+    println!("Snapshot '{}' of container '{}' created.", snapshot, name);
+    println!("Run 'claudepod restore {}' to return to it.", snapshot);
+    Ok(())
+}
+
+fn cmd_restore(container_name_arg: Option<&str>, snapshot_name: &str) -> Result<()> {
+    let mut index = ProjectsIndex::load()?;
+    let (project_id, _project_dir, mut data) = ensure_project_exists(&mut index)?;
+    index.save()?;
+
+    let info = data.get_container_mut(container_name_arg)?;
+    let snapshot = info.snapshot(snapshot_name).cloned().ok_or_else(|| {
+        ClaudepodError::Other(format!(
+            "Snapshot '{}' not found. Run 'claudepod snapshots' to list them.",
+            snapshot_name
+        ))
+    })?;
+    let docker_name = container_name(&info.uuid);
+    let runtime = info.config.docker.container_runtime.clone();
+
+    if !DockerClient::image_exists(&snapshot.image_tag, &runtime) {
+        return Err(ClaudepodError::Docker(format!(
+            "Snapshot image {} no longer exists.",
+            snapshot.image_tag
+        )));
+    }
+
+    if DockerClient::container_exists(&docker_name, &runtime) {
+        println!("Removing current container...");
+        DockerClient::remove_container(&docker_name, &runtime)?;
+    }
+
+    // The container gets its own tag, so removing the snapshot later does
+    // not take the image out from under it
+    let image_tag = format!(
+        "claudepod:restore-{}",
+        &generate_uuid().replace('-', "")[..12]
+    );
+    DockerClient::tag_image(&snapshot.image_tag, &image_tag, &runtime)?;
+    info.image_tag = image_tag;
+    save_project_data(&project_id, &data)?;
+
+    println!("Restored snapshot '{}'.", snapshot.name);
+    println!("The container will be recreated from it on next run.");
+    Ok(())
+}
+
+fn cmd_rollback(container_name_arg: Option<&str>, sessions: usize) -> Result<()> {
+    let mut index = ProjectsIndex::load()?;
+    let (_project_id, _project_dir, data) = ensure_project_exists(&mut index)?;
+    index.save()?;
+
+    let (name, info) = data.get_container(container_name_arg)?;
+    let autos: Vec<&Snapshot> = info.snapshots.iter().filter(|s| s.auto).collect();
+    if autos.is_empty() {
+        return Err(ClaudepodError::Other(format!(
+            "No automatic snapshots of container '{}'. Set auto_snapshot in the profile's [session] section.",
+            name
+        )));
+    }
+    if sessions == 0 || sessions > autos.len() {
+        return Err(ClaudepodError::Other(format!(
+            "Can go back 1 to {} session(s) for container '{}'.",
+            autos.len(),
+            name
+        )));
+    }
+
+    let snapshot = autos[autos.len() - sessions];
+    println!(
+        "Rolling back to before the session of {} (snapshot '{}')...",
+        snapshot.created_at.format("%Y-%m-%d %H:%M:%S"),
+        snapshot.name
+    );
+    cmd_restore(Some(name), &snapshot.name)
+}
+
+/// Project directory for the session commands, which must be a git repository
+fn session_project_dir() -> Result<PathBuf> {
+    let mut index = ProjectsIndex::load()?;
+    let (_project_id, project_dir) = find_project(&mut index)?;
+    index.save()?;
+
+    if !sessions::is_repo(&project_dir) {
+        return Err(ClaudepodError::Other(format!(
+            "Project '{}' is not a git repository.",
+            project_dir.display()
+        )));
+    }
+    Ok(project_dir)
+}
+
+fn cmd_sessions() -> Result<()> {
+    let project_dir = session_project_dir()?;
+    let list = sessions::list(&project_dir)?;
+
+    if list.is_empty() {
+        println!("No checkpointed sessions.");
+        println!("\nSet git_checkpoint = true in the profile's [session] section to record them.");
+        return Ok(());
+    }
+
+    println!("Sessions (newest first):");
+    for session in &list {
+        let summary = match &session.end {
+            None => "(did not finish)".to_string(),
+            Some(_) => {
+                let stat = sessions::diffstat(&project_dir, session)?;
+                if stat.is_empty() {
+                    "(no changes)".to_string()
+                } else {
+                    stat
+                }
+            }
+        };
+        println!("  {}  {}", session.id, summary);
+    }
+    Ok(())
+}
+
+fn cmd_session(action: SessionAction) -> Result<()> {
+    let project_dir = session_project_dir()?;
+
+    match action {
+        SessionAction::Revert { id } => {
+            sessions::revert(&project_dir, &id)?;
+            println!("Reverted the changes of session {}.", id);
+            Ok(())
+        }
+    }
+}
+
+fn cmd_snapshots(container_name_arg: Option<&str>, action: SnapshotsAction) -> Result<()> {
+    let mut index = ProjectsIndex::load()?;
+    let (project_id, _project_dir, mut data) = ensure_project_exists(&mut index)?;
+    index.save()?;
+
+    match action {
+        SnapshotsAction::List => {
+            let (name, info) = data.get_container(container_name_arg)?;
+            if info.snapshots.is_empty() {
+                println!("No snapshots of container '{}'.", name);
+                println!("\nRun 'claudepod snapshot [NAME]' to create one.");
+                return Ok(());
+            }
+
+            let runtime = &info.config.docker.container_runtime;
+            println!("Snapshots of container '{}':", name);
+            for snapshot in &info.snapshots {
+                let missing = if DockerClient::image_exists(&snapshot.image_tag, runtime) {
+                    ""
+                } else {
+                    " [image missing]"
+                };
+                let auto = if snapshot.auto { " (auto)" } else { "" };
+                println!(
+                    "  {}  {}{}{}",
+                    snapshot.name,
+                    snapshot.created_at.format("%Y-%m-%d %H:%M:%S"),
+                    auto,
+                    missing
+                );
+                if !snapshot.description.is_empty() {
+                    println!("      {}", snapshot.description);
+                }
+            }
+            Ok(())
+        }
+        SnapshotsAction::Remove { name } => {
+            let info = data.get_container_mut(container_name_arg)?;
+            let snapshot = info.snapshot(&name).cloned().ok_or_else(|| {
+                ClaudepodError::Other(format!(
+                    "Snapshot '{}' not found. Run 'claudepod snapshots' to list them.",
+                    name
+                ))
+            })?;
+
+            let runtime = &info.config.docker.container_runtime;
+            if DockerClient::image_exists(&snapshot.image_tag, runtime) {
+                DockerClient::remove_image(&snapshot.image_tag, runtime)?;
+            }
+            info.snapshots.retain(|s| s.name != name);
+            save_project_data(&project_id, &data)?;
+
+            println!("Removed snapshot '{}'.", name);
+            Ok(())
+        }
+    }
+}
+
+/// The overlay workspace of a container, with the project it belongs to
+fn overlay_workspace(container_name_arg: Option<&str>) -> Result<(Workspace, PathBuf)> {
+    let mut index = ProjectsIndex::load()?;
+    let (project_id, project_dir, data) = ensure_project_exists(&mut index)?;
+    index.save()?;
+
+    let (name, info) = data.get_container(container_name_arg)?;
+    if info.config.docker.workspace_mode != "overlay" {
+        return Err(ClaudepodError::Other(format!(
+            "Container '{}' mounts the project directly; changes are already in it.\n\
+             Set workspace_mode = \"overlay\" in the profile's [docker] section to review them first.",
+            name
+        )));
+    }
+
+    let workspace = Workspace::new(paths::workspace_dir(&project_id, name));
+    Ok((workspace, project_dir))
+}
+
+/// Print changes in `git status --short` form
+fn print_changes(changes: &[Change]) {
+    for change in changes {
+        match &change.link {
+            Some(target) => println!("  {} {} -> {}", change.status(), change.path, target),
+            None => println!("  {} {}", change.status(), change.path),
+        }
+    }
+}
+
+/// A side of a diff involving a symlink, as a line of text (diff itself
+/// would follow the link)
+fn describe_diff_side(path: &Path) -> String {
+    match fs::read_link(path) {
+        Ok(target) => format!("symlink to {}", target.display()),
+        Err(_) => "regular file".to_string(),
+    }
+}
+
+fn cmd_diff(container_name_arg: Option<&str>, stat: bool) -> Result<()> {
+    let (workspace, project_dir) = overlay_workspace(container_name_arg)?;
+    let changes = workspace.changes()?;
+
+    if changes.is_empty() {
+        println!("No pending changes.");
+        return Ok(());
+    }
+
+    if stat {
+        print_changes(&changes);
+        return Ok(());
+    }
+
+    let tree = workspace.tree();
+    for change in &changes {
+        let old = match change.kind {
+            ChangeKind::Added => PathBuf::from("/dev/null"),
+            _ => project_dir.join(&change.path),
+        };
+        let new = match change.kind {
+            ChangeKind::Deleted => PathBuf::from("/dev/null"),
+            _ => tree.join(&change.path),
+        };
+
+        if change.link.is_some() || fs::read_link(&old).is_ok() {
+            println!("--- a/{}", change.path);
+            println!("+++ b/{}", change.path);
+            if change.kind != ChangeKind::Added {
+                println!("-{}", describe_diff_side(&old));
+            }
+            if change.kind != ChangeKind::Deleted {
+                println!("+{}", describe_diff_side(&new));
+            }
+            continue;
+        }
+
+        // diff exits with 1 when the files differ
+        std::process::Command::new("diff")
+            .args(["-u", "--label"])
+            .arg(format!("a/{}", change.path))
+            .arg("--label")
+            .arg(format!("b/{}", change.path))
+            .arg(old)
+            .arg(new)
+            .status()
+            .map_err(|e| ClaudepodError::Other(format!("Failed to run diff: {}", e)))?;
+    }
+    Ok(())
+}
+
+fn cmd_apply(container_name_arg: Option<&str>, paths: &[String], force: bool) -> Result<()> {
+    let (workspace, project_dir) = overlay_workspace(container_name_arg)?;
+    let report = workspace.apply(&project_dir, paths, force)?;
+
+    if report.applied.is_empty() && report.conflicts.is_empty() && report.refused.is_empty() {
+        println!("No pending changes.");
+        return Ok(());
+    }
+
+    if !report.applied.is_empty() {
+        println!("Applied {} change(s):", report.applied.len());
+        print_changes(&report.applied);
+    }
+    if !report.conflicts.is_empty() {
+        if !report.applied.is_empty() {
+            println!();
+        }
+        println!(
+            "Skipped {} change(s) to files that also changed in the project:",
+            report.conflicts.len()
+        );
+        print_changes(&report.conflicts);
+        println!("\nUse --force to overwrite them, or 'claudepod discard <PATH>' to drop them.");
+    }
+    if !report.refused.is_empty() {
+        if !report.applied.is_empty() || !report.conflicts.is_empty() {
+            println!();
+        }
+        println!(
+            "Refused {} change(s) that would write outside the project or over a directory:",
+            report.refused.len()
+        );
+        for (change, reason) in &report.refused {
+            println!("  {} {}  ({})", change.status(), change.path, reason);
+        }
+        println!(
+            "\nCopy them by hand if they are intended, or 'claudepod discard <PATH>' to drop them."
+        );
+    }
+    Ok(())
+}
+
+fn cmd_discard(container_name_arg: Option<&str>, paths: &[String]) -> Result<()> {
+    let (workspace, project_dir) = overlay_workspace(container_name_arg)?;
+    let discarded = workspace.discard(&project_dir, paths)?;
+
+    if discarded.is_empty() {
+        println!("No pending changes.");
+    } else {
+        println!("Discarded {} change(s):", discarded.len());
+        print_changes(&discarded);
+    }
+    Ok(())
+}
+
 fn cmd_mount(container_name_arg: Option<&str>, action: MountAction) -> Result<()> {
     let mut index = ProjectsIndex::load()?;
     let (project_id, _project_dir, mut data) = ensure_project_exists(&mut index)?;
@@ -1062,9 +2259,7 @@ fn cmd_mount(container_name_arg: Option<&str>, action: MountAction) -> Result<()
     match action {
         MountAction::List => {
             let (_name, info) = data.get_container(container_name_arg)?;
-            let docker = info.docker.as_ref().ok_or_else(|| {
-                ClaudepodError::Other("No frozen config found for container.".to_string())
-            })?;
+            let docker = &info.config.docker;
 
             if docker.volumes.is_empty() {
                 println!("No volume mounts configured.");
@@ -1095,9 +2290,7 @@ fn cmd_mount(container_name_arg: Option<&str>, action: MountAction) -> Result<()
             };
 
             let info = data.get_container_mut(container_name_arg)?;
-            let docker = info.docker.as_mut().ok_or_else(|| {
-                ClaudepodError::Other("No frozen config found for container.".to_string())
-            })?;
+            let docker = &mut info.config.docker;
 
             // Check for duplicate
             if docker.volumes.iter().any(|v| v.host == host) {
@@ -1110,20 +2303,7 @@ fn cmd_mount(container_name_arg: Option<&str>, action: MountAction) -> Result<()
             let docker_name = container_name(&info.uuid);
             let runtime = docker.container_runtime.clone();
 
-            // If container exists, commit its state to preserve filesystem changes
-            if DockerClient::container_exists(&docker_name, &runtime) {
-                println!("Stopping container...");
-                if DockerClient::container_is_running(&docker_name, &runtime) {
-                    DockerClient::stop_container(&docker_name, &runtime)?;
-                }
-
-                let new_image_tag = format!("claudepod:mount-{}", &generate_uuid().replace('-', "")[..12]);
-                println!("Committing container state to {}...", new_image_tag);
-                DockerClient::commit_container(&docker_name, &new_image_tag, &runtime)?;
-
-                println!("Removing old container...");
-                DockerClient::remove_container(&docker_name, &runtime)?;
-
+            if let Some(new_image_tag) = commit_for_recreate(&docker_name, &runtime, "mount")? {
                 info.image_tag = new_image_tag;
             }
 
@@ -1140,9 +2320,7 @@ fn cmd_mount(container_name_arg: Option<&str>, action: MountAction) -> Result<()
             let expanded = shellexpand::tilde(&path).to_string();
 
             let info = data.get_container_mut(container_name_arg)?;
-            let docker = info.docker.as_mut().ok_or_else(|| {
-                ClaudepodError::Other("No frozen config found for container.".to_string())
-            })?;
+            let docker = &mut info.config.docker;
 
             let original_len = docker.volumes.len();
             docker.volumes.retain(|v| v.host != expanded && v.container != expanded);
@@ -1157,29 +2335,309 @@ fn cmd_mount(container_name_arg: Option<&str>, action: MountAction) -> Result<()
             let docker_name = container_name(&info.uuid);
             let runtime = docker.container_runtime.clone();
 
-            // If container exists, commit its state to preserve filesystem changes
-            if DockerClient::container_exists(&docker_name, &runtime) {
-                println!("Stopping container...");
-                if DockerClient::container_is_running(&docker_name, &runtime) {
-                    DockerClient::stop_container(&docker_name, &runtime)?;
+            if let Some(new_image_tag) = commit_for_recreate(&docker_name, &runtime, "mount")? {
+                info.image_tag = new_image_tag;
+            }
+
+            save_project_data(&project_id, &data)?;
+
+            println!("Removed mount for '{}'.", path);
+            println!("The container will be recreated without the mount on next run.");
+
+            Ok(())
+        }
+    }
+}
+
+fn cmd_port(container_name_arg: Option<&str>, action: PortAction) -> Result<()> {
+    let mut index = ProjectsIndex::load()?;
+    let (project_id, _project_dir, mut data) = ensure_project_exists(&mut index)?;
+    index.save()?;
+
+    match action {
+        PortAction::List => {
+            let (_name, info) = data.get_container(container_name_arg)?;
+            let docker = &info.config.docker;
+
+            if docker.ports.is_empty() {
+                println!("No ports configured.");
+            } else {
+                println!("Configured ports:");
+                for port in &docker.ports {
+                    let host = if port.host == 0 {
+                        "(any)".to_string()
+                    } else {
+                        port.host.to_string()
+                    };
+                    println!(
+                        "  {}:{} -> {}/{}",
+                        port.address, host, port.container, port.protocol
+                    );
+                }
+            }
+
+            let docker_name = container_name(&info.uuid);
+            let runtime = &docker.container_runtime;
+            if DockerClient::container_exists(&docker_name, runtime) {
+                let bindings = DockerClient::port_bindings(&docker_name, runtime)?;
+                if bindings.is_empty() {
+                    println!("No active port bindings.");
+                } else {
+                    println!("Active bindings:");
+                    for (container_port, host) in bindings {
+                        println!("  {} -> {}", host, container_port);
+                    }
                 }
+            }
+            Ok(())
+        }
+        PortAction::Add { port, address } => {
+            let mut new_port = PortMapping::parse(&port)?;
+            if let Some(address) = address {
+                new_port.address = address;
+                new_port.validate()?;
+            }
+
+            let info = data.get_container_mut(container_name_arg)?;
+            let docker = &mut info.config.docker;
+
+            // Check for duplicate
+            let duplicate = docker.ports.iter().any(|p| {
+                p.protocol == new_port.protocol
+                    && (p.container == new_port.container
+                        || (new_port.host != 0 && p.host == new_port.host))
+            });
+            if duplicate {
+                return Err(ClaudepodError::Other(format!(
+                    "Port '{}' is already published.",
+                    port
+                )));
+            }
+
+            let docker_name = container_name(&info.uuid);
+            let runtime = docker.container_runtime.clone();
 
-                let new_image_tag = format!("claudepod:mount-{}", &generate_uuid().replace('-', "")[..12]);
-                println!("Committing container state to {}...", new_image_tag);
-                DockerClient::commit_container(&docker_name, &new_image_tag, &runtime)?;
+            if let Some(new_image_tag) = commit_for_recreate(&docker_name, &runtime, "port")? {
+                info.image_tag = new_image_tag;
+            }
 
-                println!("Removing old container...");
-                DockerClient::remove_container(&docker_name, &runtime)?;
+            println!(
+                "Added port: {}:{} -> {}/{}",
+                new_port.address, new_port.host, new_port.container, new_port.protocol
+            );
+            docker.ports.push(new_port);
+            save_project_data(&project_id, &data)?;
 
+            println!("The container will be recreated with the new port on next run.");
+
+            Ok(())
+        }
+        PortAction::Remove { port } => {
+            let (container_port, protocol) = match port.split_once('/') {
+                Some((p, proto)) => (p, proto),
+                None => (port.as_str(), "tcp"),
+            };
+            let container_port: u16 = container_port
+                .parse()
+                .map_err(|_| ClaudepodError::Other(format!("Invalid port '{}'.", port)))?;
+
+            let info = data.get_container_mut(container_name_arg)?;
+            let docker = &mut info.config.docker;
+
+            let original_len = docker.ports.len();
+            docker
+                .ports
+                .retain(|p| !(p.container == container_port && p.protocol == protocol));
+
+            if docker.ports.len() == original_len {
+                return Err(ClaudepodError::Other(format!(
+                    "No published port matching '{}'.",
+                    port
+                )));
+            }
+
+            let docker_name = container_name(&info.uuid);
+            let runtime = docker.container_runtime.clone();
+
+            if let Some(new_image_tag) = commit_for_recreate(&docker_name, &runtime, "port")? {
                 info.image_tag = new_image_tag;
             }
 
             save_project_data(&project_id, &data)?;
 
-            println!("Removed mount for '{}'.", path);
-            println!("The container will be recreated without the mount on next run.");
+            println!("Removed port '{}'.", port);
+            println!("The container will be recreated without the port on next run.");
+
+            Ok(())
+        }
+    }
+}
+
+/// Print completion candidates, one per line. Never prompts, and prints
+/// nothing when there is no project or container to draw names from.
+fn cmd_complete(container_name_arg: Option<&str>, kind: CandidateKind) -> Result<()> {
+    let project_data = || {
+        let mut index = ProjectsIndex::load().ok()?;
+        let (project_id, _) = find_project(&mut index).ok()?;
+        load_project_data(&project_id).ok()
+    };
+
+    let candidates: Vec<String> = match kind {
+        CandidateKind::Profiles => Profile::list_available().unwrap_or_default(),
+        CandidateKind::Containers => project_data()
+            .map(|data| data.list_containers().into_iter().cloned().collect())
+            .unwrap_or_default(),
+        CandidateKind::Commands => project_data()
+            .and_then(|data| {
+                let (_, info) = data.get_container(container_name_arg).ok()?;
+                let mut names: Vec<String> = info.config.cmd.commands.keys().cloned().collect();
+                names.sort();
+                Some(names)
+            })
+            .unwrap_or_default(),
+    };
+
+    for candidate in candidates {
+        println!("{}", candidate);
+    }
+    Ok(())
+}
+
+/// Load a profile given by name, or by path if the argument is an existing file
+fn load_profile_arg(arg: &str) -> Result<Profile> {
+    let path = std::path::Path::new(arg);
+    if path.is_file() {
+        Profile::from_file(path)
+    } else {
+        Profile::load(arg)
+    }
+}
+
+fn cmd_profile(action: ProfileAction) -> Result<()> {
+    Profile::ensure_default()?;
+
+    match action {
+        ProfileAction::List => {
+            let profiles = Profile::list_available()?;
+            println!("Profiles ({}):", paths::profiles_dir().display());
+            for name in profiles {
+                println!("  {}", name);
+            }
+            Ok(())
+        }
+        ProfileAction::Show { name } => {
+            let profile = Profile::load(&name)?;
+            print!("{}", profile.to_toml_string()?);
+            Ok(())
+        }
+        ProfileAction::New { name, from } => {
+            Profile::validate_name(&name)?;
+            Profile::validate_name(&from)?;
+            let path = Profile::path(&name);
+            if path.exists() {
+                return Err(ClaudepodError::Other(format!(
+                    "Profile '{}' already exists at {}",
+                    name,
+                    path.display()
+                )));
+            }
+
+            // Copy the file as-is so comments are kept
+            Profile::load(&from)?;
+            fs::copy(Profile::path(&from), &path)?;
 
+            println!("Created profile '{}' from '{}'.", name, from);
+            println!("Edit it with 'claudepod profile edit {}'.", name);
+            Ok(())
+        }
+        ProfileAction::Edit { name } => edit_profile(&name),
+        ProfileAction::Validate { file } => {
+            Profile::from_file(&file)?.check_host()?;
+            println!("{} is valid.", file.display());
+            Ok(())
+        }
+        ProfileAction::Diff { a, b } => {
+            let diff = load_profile_arg(&a)?.diff(&load_profile_arg(&b)?)?;
+
+            if diff.is_empty() {
+                println!("No differences.");
+            }
+            for field in &diff {
+                println!("{}", format_field_diff(field));
+            }
             Ok(())
         }
     }
 }
+
+/// Edit a copy of the profile in $VISUAL/$EDITOR and only replace the profile
+/// once the edited copy validates
+fn edit_profile(name: &str) -> Result<()> {
+    Profile::validate_name(name)?;
+    let path = Profile::path(name);
+    if !path.exists() {
+        Profile::load(name)?;
+    }
+
+    let original = fs::read_to_string(&path)?;
+    // Created exclusively with a random name, so nothing planted in a shared
+    // temp directory gets written through. Removed when dropped.
+    let mut edit_file = tempfile::Builder::new()
+        .prefix(&format!("claudepod-profile-{}-", name))
+        .suffix(".toml")
+        .tempfile()?;
+    edit_file.write_all(original.as_bytes())?;
+    edit_file.flush()?;
+    let edit_path = edit_file.path().to_path_buf();
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    let result = loop {
+        // Run through the shell so editors with arguments ("code --wait") work
+        let status = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$1\"", editor))
+            .arg("sh")
+            .arg(&edit_path)
+            .status()
+            .map_err(|e| ClaudepodError::Other(format!("Failed to run editor: {}", e)))?;
+        if !status.success() {
+            break Err(ClaudepodError::Other(format!(
+                "Editor '{}' exited with {}",
+                editor, status
+            )));
+        }
+
+        let edited = fs::read_to_string(&edit_path)?;
+        if edited == original {
+            println!("No changes.");
+            break Ok(());
+        }
+
+        match Profile::from_str(&edited) {
+            Ok(_) => {
+                fs::write(&path, edited)?;
+                println!("Saved profile '{}'.", name);
+                break Ok(());
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                print!("Edit again? [Y/n] ");
+                io::stdout().flush()?;
+
+                let mut input = String::new();
+                io::stdin().read_line(&mut input)?;
+                let input = input.trim().to_lowercase();
+                if !(input.is_empty() || input == "y" || input == "yes") {
+                    println!("Changes discarded.");
+                    break Ok(());
+                }
+            }
+        }
+    };
+
+    drop(edit_file);
+    result
+}
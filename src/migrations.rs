@@ -0,0 +1,361 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+use crate::error::{ClaudepodError, Result};
+use crate::profile::Profile;
+
+/// One upgrade step of a file format, from `version - 1` to `version`
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut Table) -> Result<()>,
+}
+
+/// Steps for project.toml (`ProjectData`)
+pub const PROJECT_MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "freeze the full profile into each container",
+    apply: project_v1,
+}];
+
+/// Steps for projects.toml (`ProjectsIndex`)
+pub const INDEX_MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "add schema_version",
+    apply: |_| Ok(()),
+}];
+
+/// Steps for a single `ContainerInfo` (inside project.toml, or the config
+/// stored in a saved tar file)
+pub const CONTAINER_MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "freeze the full profile",
+    apply: container_v1,
+}];
+
+/// Profile sections frozen at the top level of a version 0 `ContainerInfo`,
+/// with their key in `Profile`
+const V0_CONTAINER_SECTIONS: [(&str, &str); 2] = [("docker", "docker"), ("commands", "cmd")];
+
+/// Latest schema version of a format
+pub fn latest(migrations: &[Migration]) -> u32 {
+    migrations.last().map_or(0, |m| m.version)
+}
+
+/// Apply the steps newer than the table's `schema_version` (0 if missing).
+/// Returns the version the table had before.
+pub fn migrate(table: &mut Table, migrations: &[Migration]) -> Result<u32> {
+    let version = match table.get("schema_version") {
+        None => 0,
+        Some(value) => value
+            .as_integer()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| ClaudepodError::Other(format!("Invalid schema_version: {}", value)))?,
+    };
+
+    if version > latest(migrations) {
+        return Err(ClaudepodError::Other(format!(
+            "Schema version {} is newer than this claudepod supports ({}). Please upgrade claudepod.",
+            version,
+            latest(migrations)
+        )));
+    }
+
+    for migration in migrations.iter().filter(|m| m.version > version) {
+        (migration.apply)(table).map_err(|e| {
+            ClaudepodError::Other(format!(
+                "Migration to schema version {} ({}) failed: {}",
+                migration.version, migration.description, e
+            ))
+        })?;
+        table.insert(
+            "schema_version".to_string(),
+            Value::Integer(migration.version.into()),
+        );
+    }
+
+    Ok(version)
+}
+
+/// Parse TOML content in any supported version of a format
+pub fn parse<T: DeserializeOwned>(content: &str, migrations: &[Migration]) -> Result<T> {
+    let mut table: Table = toml::from_str(content)?;
+    migrate(&mut table, migrations)?;
+    Ok(Value::Table(table).try_into()?)
+}
+
+/// Where the original of an upgraded file is kept, e.g. `project.toml.v0.bak`
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}.v{}.bak", file_name, version))
+}
+
+/// Load a versioned TOML file. If it was written in an older version, the
+/// original is backed up next to it and the upgraded file written back.
+pub fn load_file<T: DeserializeOwned + Serialize>(
+    path: &Path,
+    migrations: &[Migration],
+) -> Result<T> {
+    let content = fs::read_to_string(path)?;
+    let mut table: Table = toml::from_str(&content)?;
+    let version = migrate(&mut table, migrations)?;
+    let value: T = Value::Table(table).try_into()?;
+
+    if version < latest(migrations) {
+        fs::write(backup_path(path, version), &content)?;
+        fs::write(path, toml::to_string_pretty(&value)?)?;
+    }
+
+    Ok(value)
+}
+
+/// project.toml v1: migrate every container to the full frozen profile
+fn project_v1(table: &mut Table) -> Result<()> {
+    if let Some(Value::Table(containers)) = table.get_mut("containers") {
+        for (_, container) in containers.iter_mut() {
+            if let Value::Table(container) = container {
+                migrate(container, CONTAINER_MIGRATIONS)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// ContainerInfo v1: the complete profile is frozen under `config`, along
+/// with the hash of the profile the image was built from. Sections that v0
+/// did not freeze come from the profile if it still exists (as they did at
+/// run time), otherwise from the defaults.
+fn container_v1(table: &mut Table) -> Result<()> {
+    let name = table
+        .get("profile")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    let profile = match Profile::load(&name) {
+        Ok(profile) => Some(profile),
+        Err(ClaudepodError::ProfileNotFound(_)) => {
+            eprintln!(
+                "Warning: profile '{}' no longer exists; settings it didn't freeze into the container use defaults",
+                name
+            );
+            None
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut config = Value::try_from(profile.clone().unwrap_or_else(Profile::default))?;
+    if let Value::Table(sections) = &mut config {
+        for (old, new) in V0_CONTAINER_SECTIONS {
+            if let Some(section) = table.remove(old) {
+                sections.insert(new.to_string(), section);
+            }
+        }
+    }
+    let config: Profile = config.try_into()?;
+
+    // v0 only recorded the image tag, a prefix of the hash the image was built
+    // from. Without a tag the image was always the profile's current one.
+    let current_hash = profile.as_ref().unwrap_or(&config).compute_hash()?;
+    let tag = table
+        .get("image_tag")
+        .and_then(Value::as_str)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string);
+    let profile_hash = match tag {
+        None => {
+            table.insert(
+                "image_tag".to_string(),
+                Value::String(format!("claudepod:{}", &current_hash[..12])),
+            );
+            current_hash
+        }
+        // Left unknown (empty) unless the tag shows it's the current profile's
+        Some(tag) if profile.is_some() && tag == format!("claudepod:{}", &current_hash[..12]) => {
+            current_hash
+        }
+        Some(_) => String::new(),
+    };
+
+    table.insert("profile_hash".to_string(), Value::String(profile_hash));
+    table.insert("config".to_string(), Value::try_from(&config)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{ContainerInfo, ProjectData, ProjectsIndex};
+    use tempfile::TempDir;
+
+    /// A project.toml written by the last version without schema versions,
+    /// for a profile that no longer exists
+    const V0_PROJECT: &str = r#"
+default = "main"
+
+[containers.main]
+uuid = "6f0c2d4e-8a1b-4c3d-9e5f-7a8b9c0d1e2f"
+profile = "claudepod-test-deleted-profile"
+created_at = "2025-06-01T12:00:00Z"
+image_tag = "claudepod:3f9a1c2b7d4e"
+
+[containers.main.docker]
+container_runtime = "podman"
+enable_gpu = true
+gpu_driver = "all"
+interactive = true
+remove_on_exit = true
+extra_args = []
+
+[[containers.main.docker.volumes]]
+host = "$PWD"
+container = "$PWD"
+readonly = false
+
+[[containers.main.docker.volumes]]
+host = "$HOME/.claude"
+container = "/home/code/.claude"
+readonly = false
+
+[[containers.main.docker.volumes]]
+host = "$HOME/.claude.json"
+container = "/home/code/.claude.json"
+readonly = false
+
+[[containers.main.docker.tmpfs]]
+path = "/workspace/build"
+readonly = true
+size = "1m"
+
+[containers.main.commands]
+default = "claude"
+
+[containers.main.commands.bash]
+args = ""
+
+[containers.main.commands.claude]
+install = '''
+RUN mkdir -p /home/code/.npm-global && \
+    npm config set prefix /home/code/.npm-global && \
+    npm install --silent -g @anthropic-ai/claude-code'''
+args = "--dangerously-skip-permissions --max-turns 99999999"
+
+[containers.main.commands.shell]
+args = ""
+command = "bash"
+
+[containers.main.commands.zsh]
+args = ""
+"#;
+
+    /// The v0 `main` container table
+    fn v0_container() -> Table {
+        let project: Table = toml::from_str(V0_PROJECT).unwrap();
+        project["containers"]["main"].as_table().unwrap().clone()
+    }
+
+    #[test]
+    fn test_container_v1() {
+        let mut container = v0_container();
+        container.remove("image_tag");
+        let info: ContainerInfo =
+            parse(&toml::to_string(&container).unwrap(), CONTAINER_MIGRATIONS).unwrap();
+        assert_eq!(info.schema_version, 1);
+        assert_eq!(info.config.cmd.default, "claude");
+        assert_eq!(
+            info.config.cmd.commands["shell"].command.as_deref(),
+            Some("bash")
+        );
+        assert_eq!(info.config.docker.volumes.len(), 3);
+        assert_eq!(info.config.docker.tmpfs[0].path, "/workspace/build");
+        assert_eq!(info.config.network.mode, "default");
+        assert_eq!(info.profile_hash.len(), 64);
+        assert_eq!(
+            info.image_tag,
+            format!("claudepod:{}", &info.profile_hash[..12])
+        );
+
+        // A stored image tag is kept, and since it can't be matched to a
+        // profile the hash it was built from is unknown
+        let info: ContainerInfo = parse(
+            &toml::to_string(&v0_container()).unwrap(),
+            CONTAINER_MIGRATIONS,
+        )
+        .unwrap();
+        assert_eq!(info.image_tag, "claudepod:3f9a1c2b7d4e");
+        assert!(info.profile_hash.is_empty());
+    }
+
+    #[test]
+    fn test_project_v1() {
+        let current = ContainerInfo::new(
+            "current-uuid".to_string(),
+            "default",
+            "claudepod:current".to_string(),
+            Profile::default(),
+        )
+        .unwrap();
+
+        let mut old: Table = toml::from_str(V0_PROJECT).unwrap();
+        if let Some(Value::Table(containers)) = old.get_mut("containers") {
+            containers.insert("other".to_string(), Value::try_from(&current).unwrap());
+        }
+
+        let data: ProjectData = parse(&toml::to_string(&old).unwrap(), PROJECT_MIGRATIONS).unwrap();
+        assert_eq!(data.schema_version, 1);
+        assert_eq!(data.default, "main");
+        assert_eq!(data.containers["main"].schema_version, 1);
+        assert_eq!(data.containers["main"].config.cmd.default, "claude");
+        assert_eq!(data.containers["main"].image_tag, "claudepod:3f9a1c2b7d4e");
+        assert_eq!(data.containers["other"].image_tag, "claudepod:current");
+        assert_eq!(data.containers["other"].profile_hash, current.profile_hash);
+    }
+
+    #[test]
+    fn test_index_v1() {
+        let old = r#"
+[projects.abc]
+path = "/tmp/project"
+name = "project"
+created_at = "2025-01-01T00:00:00Z"
+last_accessed = "2025-01-01T00:00:00Z"
+"#;
+        let index: ProjectsIndex = parse(old, INDEX_MIGRATIONS).unwrap();
+        assert_eq!(index.schema_version, 1);
+        assert_eq!(index.projects["abc"].name, "project");
+    }
+
+    #[test]
+    fn test_newer_version_rejected() {
+        let result = parse::<ProjectsIndex>("schema_version = 99\n", INDEX_MIGRATIONS);
+        assert!(result.is_err());
+
+        let result = parse::<ProjectsIndex>("schema_version = \"1\"\n", INDEX_MIGRATIONS);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_load_file_backs_up_original() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("projects.toml");
+        let backup = dir.path().join("projects.toml.v0.bak");
+        fs::write(&path, "[projects]\n").unwrap();
+
+        let index: ProjectsIndex = load_file(&path, INDEX_MIGRATIONS).unwrap();
+        assert_eq!(index.schema_version, 1);
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "[projects]\n");
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains("schema_version = 1"));
+
+        // Already current: nothing is rewritten
+        fs::remove_file(&backup).unwrap();
+        let _: ProjectsIndex = load_file(&path, INDEX_MIGRATIONS).unwrap();
+        assert!(!backup.exists());
+    }
+}
@@ -0,0 +1,447 @@
+use chrono::Utc;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::error::{ClaudepodError, Result};
+
+/// Internal network used for containers in allowlist mode
+pub const EGRESS_NETWORK: &str = "claudepod-egress";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_HEADER_BYTES: usize = 64 * 1024;
+
+/// Ports reachable on an allowed host when its entry doesn't name one
+const DEFAULT_PORTS: [u16; 2] = [80, 443];
+
+/// Check whether a host and port are covered by the allow list.
+/// "example.com" matches exactly, "*.example.com" matches any subdomain.
+/// Entries reach ports 80 and 443 unless they name a port ("example.com:8443").
+pub fn is_allowed(host: &str, port: u16, allow: &[String]) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    allow.iter().any(|entry| {
+        let entry = entry.to_ascii_lowercase();
+        let (pattern, port_ok) = match entry.rsplit_once(':') {
+            Some((pattern, p)) => (pattern.to_string(), p.parse() == Ok(port)),
+            None => (entry.clone(), DEFAULT_PORTS.contains(&port)),
+        };
+        let host_ok = match pattern.strip_prefix("*.") {
+            Some(suffix) => host.ends_with(&format!(".{}", suffix)),
+            None => host == pattern,
+        };
+        host_ok && port_ok
+    })
+}
+
+/// Ensure the internal egress network exists, returning its gateway address
+pub fn ensure_egress_network(runtime: &str) -> Result<String> {
+    let exists = Command::new(runtime)
+        .args(["network", "inspect", EGRESS_NETWORK])
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false);
+
+    if !exists {
+        let output = Command::new(runtime)
+            .args(["network", "create", "--internal", EGRESS_NETWORK])
+            .output()
+            .map_err(|e| ClaudepodError::Docker(format!("Failed to create network: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(ClaudepodError::Docker(format!(
+                "Failed to create network {}: {}",
+                EGRESS_NETWORK,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+    }
+
+    network_gateway(runtime)
+}
+
+/// Look up the gateway of the egress network (docker and podman report it differently)
+fn network_gateway(runtime: &str) -> Result<String> {
+    let output = Command::new(runtime)
+        .args(["network", "inspect", EGRESS_NETWORK])
+        .output()
+        .map_err(|e| ClaudepodError::Docker(format!("Failed to inspect network: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(ClaudepodError::Docker(format!(
+            "Failed to inspect network {}: {}",
+            EGRESS_NETWORK,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let network = json.get(0).unwrap_or(&json);
+
+    let gateway = network
+        .pointer("/IPAM/Config")
+        .or_else(|| network.get("subnets"))
+        .and_then(|v| v.as_array())
+        .and_then(|subnets| {
+            subnets.iter().find_map(|s| {
+                s.get("Gateway")
+                    .or_else(|| s.get("gateway"))
+                    .and_then(|g| g.as_str())
+                    .filter(|g| !g.is_empty())
+                    .map(|g| g.to_string())
+            })
+        });
+
+    gateway.ok_or_else(|| {
+        ClaudepodError::Docker(format!(
+            "Could not determine gateway of network {}",
+            EGRESS_NETWORK
+        ))
+    })
+}
+
+/// A small HTTP/HTTPS forward proxy that only lets allow-listed hosts through.
+/// Runs on background threads until dropped.
+pub struct EgressProxy {
+    addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl EgressProxy {
+    /// Start the proxy on `bind` (use port 0 for an ephemeral port)
+    pub fn start(bind: &str, allow: Vec<String>, log_path: PathBuf, label: &str) -> Result<Self> {
+        let listener = TcpListener::bind(bind).map_err(|e| match e.kind() {
+            // Rootless podman keeps its networks inside its own namespace, so
+            // the gateway address doesn't exist on the host
+            io::ErrorKind::AddrNotAvailable => ClaudepodError::Other(format!(
+                "Egress proxy can't listen on {}: the network gateway is not an address of this host. \
+                 This happens with rootless podman; network.mode = \"allowlist\" needs docker or rootful podman",
+                bind
+            )),
+            _ => ClaudepodError::Other(format!("Failed to start egress proxy on {}: {}", bind, e)),
+        })?;
+        let addr = listener.local_addr()?;
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let ctx = Arc::new(ProxyContext {
+            allow,
+            log_path,
+            label: label.to_string(),
+        });
+        let stop = Arc::clone(&shutdown);
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let ctx = Arc::clone(&ctx);
+                    thread::spawn(move || {
+                        let _ = handle_client(stream, &ctx);
+                    });
+                }
+            }
+        });
+
+        Ok(Self { addr, shutdown })
+    }
+
+    /// Address the proxy is listening on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Proxy environment variables for a client on the network the proxy
+    /// listens on
+    pub fn env(&self) -> Vec<(String, String)> {
+        self.env_for(&self.addr.ip().to_string())
+    }
+
+    /// Proxy environment variables for a client reaching the proxy at `host`
+    pub fn env_for(&self, host: &str) -> Vec<(String, String)> {
+        let url = format!("http://{}:{}", host, self.addr.port());
+        vec![
+            ("HTTP_PROXY".to_string(), url.clone()),
+            ("HTTPS_PROXY".to_string(), url.clone()),
+            ("http_proxy".to_string(), url.clone()),
+            ("https_proxy".to_string(), url),
+            ("NO_PROXY".to_string(), "localhost,127.0.0.1".to_string()),
+            ("no_proxy".to_string(), "localhost,127.0.0.1".to_string()),
+        ]
+    }
+}
+
+impl Drop for EgressProxy {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake the accept loop so it notices the shutdown flag
+        let _ = TcpStream::connect_timeout(&self.addr, Duration::from_millis(200));
+    }
+}
+
+struct ProxyContext {
+    allow: Vec<String>,
+    log_path: PathBuf,
+    label: String,
+}
+
+impl ProxyContext {
+    fn log(&self, message: &str) {
+        if let Some(parent) = self.log_path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Ok(mut file) = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log_path)
+        {
+            let _ = writeln!(
+                file,
+                "{} [{}] {}",
+                Utc::now().format("%Y-%m-%d %H:%M:%S"),
+                self.label,
+                message
+            );
+        }
+    }
+}
+
+fn handle_client(client: TcpStream, ctx: &ProxyContext) -> io::Result<()> {
+    let mut reader = BufReader::new(client.try_clone()?);
+    let mut client = client;
+
+    // Read the request head (request line + headers)
+    let mut head = Vec::new();
+    loop {
+        let mut line = Vec::new();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&line);
+        if line == b"\r\n" || line == b"\n" || head.len() > MAX_HEADER_BYTES {
+            break;
+        }
+    }
+
+    let head = String::from_utf8_lossy(&head).to_string();
+    let request_line = head.lines().next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+    let version = parts.next().unwrap_or("HTTP/1.1").to_string();
+
+    let Some((host, port, rewritten)) = parse_target(&method, &target) else {
+        ctx.log(&format!("rejected malformed request: {}", request_line));
+        client.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+        return Ok(());
+    };
+
+    if !is_allowed(&host, port, &ctx.allow) {
+        ctx.log(&format!("blocked {} {}:{}", method, host, port));
+        client.write_all(
+            b"HTTP/1.1 403 Forbidden\r\nContent-Type: text/plain\r\nContent-Length: 39\r\n\r\nBlocked by claudepod network allowlist\n",
+        )?;
+        return Ok(());
+    }
+
+    let upstream = match connect_upstream(&host, port) {
+        Ok(stream) => stream,
+        Err(e) => {
+            ctx.log(&format!("failed {} {}:{}: {}", method, host, port, e));
+            client.write_all(b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\n\r\n")?;
+            return Ok(());
+        }
+    };
+
+    let mut upstream_writer = upstream.try_clone()?;
+    if method.eq_ignore_ascii_case("CONNECT") {
+        client.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")?;
+    } else {
+        // Forward plain HTTP with the request line in origin-form
+        let rest = head.split_once('\n').map(|(_, r)| r).unwrap_or_default();
+        upstream_writer
+            .write_all(format!("{} {} {}\r\n", method, rewritten, version).as_bytes())?;
+        upstream_writer.write_all(rest.as_bytes())?;
+    }
+
+    // Anything the client sent after the head is still buffered in `reader`
+    let buffered = reader.buffer().to_vec();
+    upstream_writer.write_all(&buffered)?;
+
+    let client_reader = reader.into_inner();
+    let mut upstream_reader = upstream;
+    let to_upstream = thread::spawn(move || {
+        let mut client_reader = client_reader;
+        let _ = io::copy(&mut client_reader, &mut upstream_writer);
+        let _ = upstream_writer.shutdown(Shutdown::Write);
+    });
+    let _ = io::copy(&mut upstream_reader, &mut client);
+    let _ = client.shutdown(Shutdown::Write);
+    let _ = to_upstream.join();
+
+    Ok(())
+}
+
+/// Extract (host, port, origin-form path) from a proxy request target
+fn parse_target(method: &str, target: &str) -> Option<(String, u16, String)> {
+    if method.eq_ignore_ascii_case("CONNECT") {
+        let (host, port) = split_host_port(target, 443)?;
+        return Some((host, port, String::new()));
+    }
+
+    let rest = target.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(idx) => (&rest[..idx], &rest[idx..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = split_host_port(authority, 80)?;
+    Some((host, port, path.to_string()))
+}
+
+fn split_host_port(authority: &str, default_port: u16) -> Option<(String, u16)> {
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((h, p)) if !h.contains(':') || h.ends_with(']') => (h, p.parse().ok()?),
+        _ => (authority, default_port),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_string(), port))
+}
+
+fn connect_upstream(host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut last_err = io::Error::new(io::ErrorKind::NotFound, "no addresses resolved");
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = e,
+        }
+    }
+    Err(last_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tempfile::TempDir;
+
+    fn allow(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let list = allow(&["api.anthropic.com", "*.npmjs.org", "git.example.com:2222"]);
+        assert!(is_allowed("api.anthropic.com", 443, &list));
+        assert!(is_allowed("API.Anthropic.com.", 443, &list));
+        assert!(is_allowed("registry.npmjs.org", 80, &list));
+        assert!(!is_allowed("npmjs.org", 443, &list));
+        assert!(!is_allowed("evil-npmjs.org", 443, &list));
+        assert!(!is_allowed("anthropic.com", 443, &list));
+
+        // Only 80/443 unless the entry names a port
+        assert!(!is_allowed("api.anthropic.com", 22, &list));
+        assert!(is_allowed("git.example.com", 2222, &list));
+        assert!(!is_allowed("git.example.com", 443, &list));
+    }
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(
+            parse_target("CONNECT", "api.anthropic.com:443"),
+            Some(("api.anthropic.com".to_string(), 443, String::new()))
+        );
+        assert_eq!(
+            parse_target("GET", "http://example.com:8080/a?b=c"),
+            Some(("example.com".to_string(), 8080, "/a?b=c".to_string()))
+        );
+        assert_eq!(
+            parse_target("GET", "http://example.com"),
+            Some(("example.com".to_string(), 80, "/".to_string()))
+        );
+        assert_eq!(parse_target("GET", "/relative"), None);
+    }
+
+    #[test]
+    fn test_proxy_blocks_and_logs() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("egress.log");
+        let proxy = EgressProxy::start(
+            "127.0.0.1:0",
+            allow(&["api.anthropic.com"]),
+            log_path.clone(),
+            "test",
+        )
+        .unwrap();
+
+        let mut stream = TcpStream::connect(proxy.addr()).unwrap();
+        stream
+            .write_all(b"CONNECT evil.example.com:443 HTTP/1.1\r\nHost: evil.example.com\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 403"));
+
+        let log = fs::read_to_string(&log_path).unwrap();
+        assert!(log.contains("[test] blocked CONNECT evil.example.com:443"));
+    }
+
+    #[test]
+    fn test_proxy_tunnels_allowed_host() {
+        // Upstream echo server standing in for an allowed host
+        let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut conn, _) = upstream.accept().unwrap();
+            let mut buf = [0u8; 5];
+            conn.read_exact(&mut buf).unwrap();
+            conn.write_all(&buf).unwrap();
+        });
+
+        let temp_dir = TempDir::new().unwrap();
+        let proxy = EgressProxy::start(
+            "127.0.0.1:0",
+            vec![format!("127.0.0.1:{}", upstream_port)],
+            temp_dir.path().join("egress.log"),
+            "test",
+        )
+        .unwrap();
+
+        let mut stream = TcpStream::connect(proxy.addr()).unwrap();
+        stream
+            .write_all(
+                format!("CONNECT 127.0.0.1:{} HTTP/1.1\r\n\r\nhello", upstream_port).as_bytes(),
+            )
+            .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert_eq!(response, "HTTP/1.1 200 Connection Established\r\n\r\nhello");
+    }
+
+    #[test]
+    fn test_proxy_env() {
+        let temp_dir = TempDir::new().unwrap();
+        let proxy = EgressProxy::start(
+            "127.0.0.1:0",
+            vec![],
+            temp_dir.path().join("egress.log"),
+            "test",
+        )
+        .unwrap();
+        let env = proxy.env_for("10.89.0.1");
+        let url = format!("http://10.89.0.1:{}", proxy.addr().port());
+        assert!(env.contains(&("HTTPS_PROXY".to_string(), url)));
+
+        let url = format!("http://127.0.0.1:{}", proxy.addr().port());
+        assert!(proxy.env().contains(&("HTTPS_PROXY".to_string(), url)));
+    }
+}
@@ -20,6 +20,14 @@ pub fn project_dir(id: &str) -> PathBuf {
     projects_dir().join(id)
 }
 
+/// Get the overlay workspace of a container
+/// (~/.claudepod/projects/{id}/workspaces/{name})
+pub fn workspace_dir(project_id: &str, container_name: &str) -> PathBuf {
+    project_dir(project_id)
+        .join("workspaces")
+        .join(container_name)
+}
+
 /// Get the config directory (~/.config/claudepod)
 pub fn config_dir() -> PathBuf {
     dirs::config_dir()
@@ -44,6 +52,11 @@ pub fn build_dir() -> PathBuf {
     data_dir().join("build")
 }
 
+/// Get the logs directory (~/.local/share/claudepod/logs)
+pub fn logs_dir() -> PathBuf {
+    data_dir().join("logs")
+}
+
 /// Ensure all required directories exist
 pub fn ensure_dirs() -> Result<()> {
     fs::create_dir_all(claudepod_home())?;
@@ -52,6 +65,7 @@ pub fn ensure_dirs() -> Result<()> {
     fs::create_dir_all(profiles_dir())?;
     fs::create_dir_all(data_dir())?;
     fs::create_dir_all(build_dir())?;
+    fs::create_dir_all(logs_dir())?;
     Ok(())
 }
 
@@ -67,6 +81,7 @@ mod tests {
         assert!(profiles_dir().ends_with("profiles"));
         assert!(data_dir().ends_with("claudepod"));
         assert!(build_dir().ends_with("build"));
+        assert!(logs_dir().ends_with("logs"));
     }
 
     #[test]
@@ -87,6 +102,6 @@ mod tests {
     fn test_project_dir() {
         let project = project_dir("abc123");
         assert!(project.ends_with("abc123"));
-        assert!(project.starts_with(&projects_dir()));
+        assert!(project.starts_with(projects_dir()));
     }
 }
@@ -1,8 +1,8 @@
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 
 use crate::error::{ClaudepodError, Result};
 use crate::paths;
@@ -18,6 +18,24 @@ pub struct Profile {
     #[serde(default)]
     pub environment: HashMap<String, String>,
 
+    #[serde(default)]
+    pub exec_env: ExecEnvConfig,
+
+    #[serde(default)]
+    pub network: NetworkConfig,
+
+    #[serde(default)]
+    pub resources: ResourcesConfig,
+
+    #[serde(default)]
+    pub security: SecurityConfig,
+
+    #[serde(default)]
+    pub gpu: GpuConfig,
+
+    #[serde(default)]
+    pub hooks: HooksConfig,
+
     #[serde(default)]
     pub git: GitConfig,
 
@@ -29,6 +47,9 @@ pub struct Profile {
 
     #[serde(default)]
     pub shell: ShellConfig,
+
+    #[serde(default)]
+    pub session: SessionConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -51,8 +72,8 @@ pub struct DockerConfig {
     #[serde(default = "default_container_runtime")]
     pub container_runtime: String,
 
-    #[serde(default = "default_true")]
-    pub enable_gpu: bool,
+    #[serde(default)]
+    pub enable_gpu: GpuToggle,
 
     #[serde(default = "default_gpu_driver")]
     pub gpu_driver: String,
@@ -69,8 +90,34 @@ pub struct DockerConfig {
     #[serde(default)]
     pub tmpfs: Vec<TmpfsMount>,
 
+    #[serde(default)]
+    pub devices: Vec<DeviceMount>,
+
+    #[serde(default)]
+    pub ports: Vec<PortMapping>,
+
     #[serde(default)]
     pub extra_args: Vec<String>,
+
+    /// How the project is mounted: "bind" (directly) or "overlay" (a copy
+    /// whose changes are applied with `claudepod apply`)
+    #[serde(default = "default_workspace_mode")]
+    pub workspace_mode: String,
+}
+
+/// `enable_gpu` setting: `true`, `false`, or `"auto"` to enable the GPU only
+/// when the host has a usable device
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum GpuToggle {
+    Enabled(bool),
+    Mode(String),
+}
+
+impl Default for GpuToggle {
+    fn default() -> Self {
+        GpuToggle::Mode("auto".to_string())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -93,7 +140,162 @@ pub struct TmpfsMount {
     pub size: String,
 }
 
+/// Host device passed into the container (e.g. /dev/ttyUSB0, /dev/fuse, /dev/kvm)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeviceMount {
+    pub host: String,
+
+    /// Path inside the container, defaults to the host path
+    pub container: Option<String>,
+
+    /// Cgroup permissions: any combination of r(ead), w(rite) and m(knod)
+    #[serde(default = "default_device_permissions")]
+    pub permissions: String,
+}
+
+/// Container port published on the host (e.g. for a dev server)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PortMapping {
+    /// Host port, 0 lets the runtime pick a free one
+    pub host: u16,
+
+    pub container: u16,
+
+    /// "tcp" or "udp"
+    #[serde(default = "default_port_protocol")]
+    pub protocol: String,
+
+    /// Host address to bind, localhost only by default
+    #[serde(default = "default_port_address")]
+    pub address: String,
+}
+
+/// Environment applied to each `exec` at runtime (never baked into the image)
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ExecEnvConfig {
+    /// Host environment variables to pass through (e.g. ANTHROPIC_API_KEY)
+    #[serde(default)]
+    pub pass: Vec<String>,
+
+    /// Optional dotenv-style file, relative paths resolve against the project root
+    #[serde(default)]
+    pub env_file: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NetworkConfig {
+    /// Network mode: "default", "none", "host" or "allowlist"
+    #[serde(default = "default_network_mode")]
+    pub mode: String,
+
+    /// Domains reachable through the egress proxy in allowlist mode
+    /// ("example.com" matches exactly, "*.example.com" matches subdomains).
+    /// Ports 80 and 443 are allowed unless an entry names one ("example.com:8443")
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+/// Resource limits applied when the container is created
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ResourcesConfig {
+    /// Number of CPUs (fractional values allowed, e.g. 1.5)
+    pub cpus: Option<f64>,
+
+    /// Memory limit (e.g. "8g", "512m")
+    pub memory: Option<String>,
+
+    /// Memory plus swap limit, or "-1" for unlimited swap (requires `memory`)
+    pub memory_swap: Option<String>,
+
+    /// Maximum number of processes, or -1 for unlimited
+    pub pids_limit: Option<i64>,
+
+    /// Size of /dev/shm (e.g. "1g")
+    pub shm_size: Option<String>,
+
+    /// Ulimits by name, as "SOFT[:HARD]" (e.g. nofile = "1024:4096")
+    #[serde(default)]
+    pub ulimits: BTreeMap<String, String>,
+}
+
+/// Container hardening options
 #[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SecurityConfig {
+    /// Capabilities to drop (e.g. "ALL", "NET_RAW")
+    #[serde(default)]
+    pub cap_drop: Vec<String>,
+
+    /// Capabilities to add back after dropping
+    #[serde(default)]
+    pub cap_add: Vec<String>,
+
+    /// Mount the root filesystem read-only (/tmp gets a tmpfs, the home directory a volume)
+    #[serde(default)]
+    pub read_only_root: bool,
+
+    /// Prevent processes from gaining privileges (e.g. via setuid or sudo)
+    #[serde(default)]
+    pub no_new_privileges: bool,
+
+    /// Path to a custom seccomp profile (JSON), or "unconfined"
+    pub seccomp: Option<String>,
+
+    /// Grant the container user passwordless sudo
+    #[serde(default = "default_true")]
+    pub sudo: bool,
+}
+
+/// GPU vendor and devices, plus host driver injection for libraries (e.g. OptiX)
+/// that CDI and --gpus don't expose
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GpuConfig {
+    /// How host driver files get into the container: "none", "mount" (read-only
+    /// bind mounts at the same path) or "copy" (staged into the image at build time)
+    #[serde(default = "default_host_drivers")]
+    pub host_drivers: String,
+
+    /// Absolute host path globs of the driver files to inject
+    #[serde(default = "default_host_driver_globs")]
+    pub host_driver_globs: Vec<String>,
+
+    /// GPU vendor: "nvidia", "amd" or "intel"
+    #[serde(default = "default_gpu_vendor")]
+    pub vendor: String,
+
+    /// Devices to expose (empty means all). NVIDIA takes indices or UUIDs,
+    /// AMD and Intel take device nodes such as /dev/dri/renderD128
+    #[serde(default)]
+    pub devices: Vec<String>,
+}
+
+/// Lifecycle hooks, each list run in order. Hooks see CLAUDEPOD_PROJECT_DIR,
+/// CLAUDEPOD_CONTAINER and (for on_run_exit) CLAUDEPOD_EXIT_CODE.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HooksConfig {
+    /// After the container is created
+    #[serde(default)]
+    pub on_create: Vec<HookConfig>,
+
+    /// Whenever the container is started
+    #[serde(default)]
+    pub on_start: Vec<HookConfig>,
+
+    /// After each command run in the container exits
+    #[serde(default)]
+    pub on_run_exit: Vec<HookConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HookConfig {
+    /// Shell command (run with bash -c, in the project directory)
+    pub command: String,
+
+    /// Run on the host instead of inside the container
+    #[serde(default)]
+    pub host: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct GitConfig {
     #[serde(default)]
     pub user_name: String,
@@ -102,7 +304,7 @@ pub struct GitConfig {
     pub user_email: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct CommandConfig {
     /// Optional Dockerfile RUN command for installation
     pub install: Option<String>,
@@ -113,6 +315,101 @@ pub struct CommandConfig {
 
     /// Command reference (for aliases) or None to use key name as executable
     pub command: Option<String>,
+
+    /// Extra environment variables for this command
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// Working directory, relative to the project root
+    pub workdir: Option<String>,
+
+    /// User to run as (e.g. "root" for maintenance commands)
+    pub user: Option<String>,
+
+    /// Shell snippet run in the container before the command (aborts on failure)
+    pub pre: Option<String>,
+
+    /// Shell snippet run in the container after the command, whatever its exit code
+    pub post: Option<String>,
+}
+
+/// A field that differs between two profiles, with TOML-rendered values
+/// (`None` where the field is absent)
+#[derive(Debug, PartialEq)]
+pub struct FieldDiff {
+    pub path: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Field paths baked into the image by the generator; everything else is
+/// applied when the container is created
+const BUILD_FIELDS: [&str; 11] = [
+    "container.base_image",
+    "container.user",
+    "container.home_dir",
+    "container.work_dir",
+    "environment",
+    "git",
+    "dependencies",
+    "shell",
+    "security.sudo",
+    "gpu.host_drivers",
+    "gpu.host_driver_globs",
+];
+
+impl FieldDiff {
+    /// Whether the change needs an image rebuild (otherwise recreating the
+    /// container is enough)
+    pub fn requires_rebuild(&self) -> bool {
+        let under = |prefix: &str| {
+            self.path == prefix
+                || self.path.starts_with(&format!("{}.", prefix))
+                || self.path.starts_with(&format!("{}[", prefix))
+        };
+        // Command install steps are part of the Dockerfile
+        let install = self.path.starts_with("cmd.commands.") && self.path.ends_with(".install");
+        install || BUILD_FIELDS.iter().any(|prefix| under(prefix))
+    }
+}
+
+/// Flatten a TOML value into dotted paths of leaf values
+fn flatten_toml(prefix: &str, value: &toml::Value, out: &mut BTreeMap<String, toml::Value>) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        }
+    };
+
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                flatten_toml(&join(key), value, out);
+            }
+        }
+        toml::Value::Array(items) if !items.is_empty() && items.iter().all(|v| v.is_table()) => {
+            for (i, item) in items.iter().enumerate() {
+                flatten_toml(&format!("{}[{}]", prefix, i), item, out);
+            }
+        }
+        _ => {
+            out.insert(prefix.to_string(), value.clone());
+        }
+    }
+}
+
+/// A command ready to execute, with settings merged along its alias chain
+#[derive(Debug, Default, PartialEq)]
+pub struct ResolvedCommand {
+    /// Executable followed by its arguments
+    pub argv: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub workdir: Option<String>,
+    pub user: Option<String>,
+    pub pre: Option<String>,
+    pub post: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -180,7 +477,30 @@ pub struct ShellConfig {
     pub history_search: bool,
 }
 
+/// What happens around each run of the default command (an agent session)
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SessionConfig {
+    /// Snapshot the container before each session, e.g. `{ keep = 5 }`
+    #[serde(default)]
+    pub auto_snapshot: Option<AutoSnapshotConfig>,
+
+    /// Checkpoint the host project (a git ref) before and after each session
+    #[serde(default)]
+    pub git_checkpoint: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AutoSnapshotConfig {
+    /// Number of automatic snapshots to keep (older ones are removed)
+    #[serde(default = "default_auto_snapshot_keep")]
+    pub keep: usize,
+}
+
 // Default functions
+fn default_auto_snapshot_keep() -> usize {
+    5
+}
+
 fn default_container_runtime() -> String {
     "podman".to_string()
 }
@@ -209,10 +529,48 @@ fn default_gpu_driver() -> String {
     "all".to_string()
 }
 
+fn default_network_mode() -> String {
+    "default".to_string()
+}
+
+fn default_workspace_mode() -> String {
+    "bind".to_string()
+}
+
+fn default_host_drivers() -> String {
+    "none".to_string()
+}
+
+fn default_gpu_vendor() -> String {
+    "nvidia".to_string()
+}
+
+fn default_host_driver_globs() -> Vec<String> {
+    vec![
+        "/usr/share/nvidia/nvoptix.bin".to_string(),
+        "/usr/lib/x86_64-linux-gnu/libnvoptix.so.*".to_string(),
+        "/usr/lib/x86_64-linux-gnu/libnvidia-rtcore.*".to_string(),
+        "/usr/lib/x86_64-linux-gnu/libnvidia-ptxjitcompiler.*".to_string(),
+        "/usr/lib/x86_64-linux-gnu/libnvidia-gpucomp.*".to_string(),
+    ]
+}
+
 fn default_tmpfs_size() -> String {
     "1m".to_string()
 }
 
+fn default_port_protocol() -> String {
+    "tcp".to_string()
+}
+
+fn default_port_address() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_device_permissions() -> String {
+    "rwm".to_string()
+}
+
 fn default_command() -> String {
     "claude".to_string()
 }
@@ -276,7 +634,7 @@ impl Default for DockerConfig {
     fn default() -> Self {
         Self {
             container_runtime: default_container_runtime(),
-            enable_gpu: true,
+            enable_gpu: GpuToggle::default(),
             gpu_driver: default_gpu_driver(),
             interactive: true,
             remove_on_exit: true,
@@ -302,26 +660,551 @@ impl Default for DockerConfig {
                 readonly: true,
                 size: "1m".to_string(),
             }],
+            devices: vec![],
+            ports: vec![],
             extra_args: vec![],
+            workspace_mode: default_workspace_mode(),
+        }
+    }
+}
+
+impl DeviceMount {
+    /// Path inside the container
+    pub fn container_path(&self) -> &str {
+        self.container.as_deref().unwrap_or(&self.host)
+    }
+
+    /// Validate paths and permissions
+    pub fn validate(&self) -> Result<()> {
+        if !self.host.starts_with('/') || !self.container_path().starts_with('/') {
+            return Err(ClaudepodError::Validation(format!(
+                "Device paths for '{}' must be absolute",
+                self.host
+            )));
+        }
+
+        let mut seen = String::new();
+        for c in self.permissions.chars() {
+            if !"rwm".contains(c) || seen.contains(c) {
+                return Err(ClaudepodError::Validation(format!(
+                    "Invalid device permissions '{}' for {}. Use a combination of r, w and m",
+                    self.permissions, self.host
+                )));
+            }
+            seen.push(c);
+        }
+        if seen.is_empty() {
+            return Err(ClaudepodError::Validation(format!(
+                "Device permissions for {} cannot be empty",
+                self.host
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Check that the host device exists. Only meaningful on the host the
+    /// container is created on, so it isn't part of `validate`.
+    pub fn check_host(&self) -> Result<()> {
+        if !Path::new(&self.host).exists() {
+            return Err(ClaudepodError::Validation(format!(
+                "Device {} does not exist on this host",
+                self.host
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl PortMapping {
+    /// Parse a spec like "3000", "8080:3000" (HOST:CONTAINER) or "5353/udp"
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = || {
+            ClaudepodError::Validation(format!(
+                "Invalid port '{}'. Expected PORT or HOST_PORT:CONTAINER_PORT, optionally with /tcp or /udp",
+                spec
+            ))
+        };
+
+        let (ports, protocol) = match spec.split_once('/') {
+            Some((ports, protocol)) => (ports, protocol.to_string()),
+            None => (spec, default_port_protocol()),
+        };
+        let (host, container) = match ports.split_once(':') {
+            Some((host, container)) => (host, container),
+            None => (ports, ports),
+        };
+
+        let port = Self {
+            host: host.parse().map_err(|_| invalid())?,
+            container: container.parse().map_err(|_| invalid())?,
+            protocol,
+            address: default_port_address(),
+        };
+        port.validate()?;
+        Ok(port)
+    }
+
+    /// Validate the container port, protocol and bind address
+    pub fn validate(&self) -> Result<()> {
+        if self.container == 0 {
+            return Err(ClaudepodError::Validation(
+                "Container port cannot be 0".to_string(),
+            ));
+        }
+        if self.protocol != "tcp" && self.protocol != "udp" {
+            return Err(ClaudepodError::Validation(format!(
+                "Invalid port protocol '{}'. Must be tcp or udp",
+                self.protocol
+            )));
+        }
+        if self.address.parse::<std::net::IpAddr>().is_err() {
+            return Err(ClaudepodError::Validation(format!(
+                "Invalid port address '{}'. Expected an IP address",
+                self.address
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            mode: default_network_mode(),
+            allow: vec![],
+        }
+    }
+}
+
+/// Ulimit names understood by both docker and podman
+const VALID_ULIMITS: [&str; 15] = [
+    "core",
+    "cpu",
+    "data",
+    "fsize",
+    "locks",
+    "memlock",
+    "msgqueue",
+    "nice",
+    "nofile",
+    "nproc",
+    "rss",
+    "rtprio",
+    "rttime",
+    "sigpending",
+    "stack",
+];
+
+/// Parse a size like "512m", "2G" or "1048576" into bytes.
+/// Accepts an optional b/k/m/g suffix (case-insensitive, binary multiples).
+pub fn parse_size(size: &str) -> Result<u64> {
+    let invalid = || {
+        ClaudepodError::Validation(format!(
+            "Invalid size '{}'. Expected a number with optional unit b, k, m or g (e.g. \"512m\")",
+            size
+        ))
+    };
+
+    let lower = size.trim().to_ascii_lowercase();
+    let (digits, multiplier) = match lower.char_indices().last() {
+        Some((idx, 'b')) => (&lower[..idx], 1),
+        Some((idx, 'k')) => (&lower[..idx], 1 << 10),
+        Some((idx, 'm')) => (&lower[..idx], 1 << 20),
+        Some((idx, 'g')) => (&lower[..idx], 1 << 30),
+        _ => (lower.as_str(), 1),
+    };
+
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(invalid)
+}
+
+fn validate_ulimit_value(value: &str) -> bool {
+    value == "unlimited" || value == "-1" || value.parse::<u64>().is_ok()
+}
+
+impl ResourcesConfig {
+    /// Validate limits and sizes
+    pub fn validate(&self) -> Result<()> {
+        if let Some(cpus) = self.cpus {
+            if !cpus.is_finite() || cpus <= 0.0 {
+                return Err(ClaudepodError::Validation(format!(
+                    "Invalid resources.cpus '{}'. Must be greater than 0",
+                    cpus
+                )));
+            }
+        }
+
+        let memory = self.memory.as_deref().map(parse_size).transpose()?;
+
+        if let Some(ref swap) = self.memory_swap {
+            let Some(memory) = memory else {
+                return Err(ClaudepodError::Validation(
+                    "resources.memory_swap requires resources.memory to be set".to_string(),
+                ));
+            };
+            if swap != "-1" && parse_size(swap)? < memory {
+                return Err(ClaudepodError::Validation(format!(
+                    "resources.memory_swap '{}' must be at least resources.memory",
+                    swap
+                )));
+            }
+        }
+
+        if let Some(pids) = self.pids_limit {
+            if pids == 0 || pids < -1 {
+                return Err(ClaudepodError::Validation(format!(
+                    "Invalid resources.pids_limit '{}'. Must be positive, or -1 for unlimited",
+                    pids
+                )));
+            }
+        }
+
+        if let Some(ref shm) = self.shm_size {
+            parse_size(shm)?;
+        }
+
+        for (name, value) in &self.ulimits {
+            if !VALID_ULIMITS.contains(&name.as_str()) {
+                return Err(ClaudepodError::Validation(format!(
+                    "Unknown ulimit '{}'. Must be one of: {}",
+                    name,
+                    VALID_ULIMITS.join(", ")
+                )));
+            }
+            let valid = match value.split_once(':') {
+                Some((soft, hard)) => validate_ulimit_value(soft) && validate_ulimit_value(hard),
+                None => validate_ulimit_value(value),
+            };
+            if !valid {
+                return Err(ClaudepodError::Validation(format!(
+                    "Invalid ulimit {} = '{}'. Expected SOFT[:HARD] with numbers or \"unlimited\"",
+                    name, value
+                )));
+            }
         }
+
+        Ok(())
     }
 }
 
-impl Default for GitConfig {
+impl Default for SecurityConfig {
     fn default() -> Self {
         Self {
-            user_name: String::new(),
-            user_email: String::new(),
+            cap_drop: vec![],
+            cap_add: vec![],
+            read_only_root: false,
+            no_new_privileges: false,
+            seccomp: None,
+            sudo: true,
+        }
+    }
+}
+
+impl SecurityConfig {
+    /// Validate capability names
+    pub fn validate(&self) -> Result<()> {
+        for cap in self.cap_drop.iter().chain(&self.cap_add) {
+            let name = cap.strip_prefix("CAP_").unwrap_or(cap);
+            let valid =
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase() || c == '_');
+            if !valid {
+                return Err(ClaudepodError::Validation(format!(
+                    "Invalid capability '{}'. Expected an uppercase name like NET_RAW or ALL",
+                    cap
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that a custom seccomp profile exists on this host
+    pub fn check_host(&self) -> Result<()> {
+        if let Some(ref seccomp) = self.seccomp {
+            if seccomp != "unconfined" {
+                let expanded = shellexpand::full(seccomp).map_err(|e| {
+                    ClaudepodError::Validation(format!("Failed to expand seccomp path: {}", e))
+                })?;
+                if !Path::new(expanded.as_ref()).is_file() {
+                    return Err(ClaudepodError::Validation(format!(
+                        "Seccomp profile not found: {}",
+                        expanded
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for GpuConfig {
+    fn default() -> Self {
+        Self {
+            host_drivers: default_host_drivers(),
+            host_driver_globs: default_host_driver_globs(),
+            vendor: default_gpu_vendor(),
+            devices: Vec::new(),
+        }
+    }
+}
+
+impl GpuConfig {
+    /// Validate the vendor, device list, injection mode and globs
+    pub fn validate(&self) -> Result<()> {
+        let valid_vendors = ["nvidia", "amd", "intel"];
+        if !valid_vendors.contains(&self.vendor.as_str()) {
+            return Err(ClaudepodError::Validation(format!(
+                "Invalid gpu.vendor '{}'. Must be one of: {}",
+                self.vendor,
+                valid_vendors.join(", ")
+            )));
+        }
+
+        for device in &self.devices {
+            let valid = if self.vendor == "nvidia" {
+                !device.is_empty() && !device.contains(['/', ',', '='])
+            } else {
+                device.starts_with("/dev/")
+            };
+            if !valid {
+                return Err(ClaudepodError::Validation(format!(
+                    "Invalid gpu.devices entry '{}' for vendor {}. Expected {}",
+                    device,
+                    self.vendor,
+                    if self.vendor == "nvidia" {
+                        "an index or UUID"
+                    } else {
+                        "a device node under /dev"
+                    }
+                )));
+            }
+        }
+
+        let valid_modes = ["none", "mount", "copy"];
+        if !valid_modes.contains(&self.host_drivers.as_str()) {
+            return Err(ClaudepodError::Validation(format!(
+                "Invalid gpu.host_drivers '{}'. Must be one of: {}",
+                self.host_drivers,
+                valid_modes.join(", ")
+            )));
+        }
+
+        for pattern in &self.host_driver_globs {
+            if !pattern.starts_with('/') {
+                return Err(ClaudepodError::Validation(format!(
+                    "gpu.host_driver_globs entry '{}' must be an absolute path",
+                    pattern
+                )));
+            }
+            glob::Pattern::new(pattern).map_err(|e| {
+                ClaudepodError::Validation(format!("Invalid glob '{}': {}", pattern, e))
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Expand the globs into the host files (and symlinks) that currently exist,
+    /// sorted and without duplicates
+    pub fn resolve_host_drivers(&self) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+
+        for pattern in &self.host_driver_globs {
+            let entries = glob::glob(pattern).map_err(|e| {
+                ClaudepodError::Validation(format!("Invalid glob '{}': {}", pattern, e))
+            })?;
+            for path in entries.flatten() {
+                let is_file = fs::symlink_metadata(&path)
+                    .map(|m| m.is_file() || m.file_type().is_symlink())
+                    .unwrap_or(false);
+                if is_file {
+                    files.push(path);
+                }
+            }
+        }
+
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+}
+
+impl ExecEnvConfig {
+    /// Resolve the variables to set for an exec, in increasing precedence:
+    /// env_file entries, passed-through host variables, then `overrides`
+    /// (`KEY=VAL`, or bare `KEY` to take the value from the host).
+    /// Host variables are looked up through `host_var`.
+    pub fn resolve(
+        &self,
+        project_dir: &Path,
+        overrides: &[String],
+        host_var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Vec<(String, String)>> {
+        let mut vars: Vec<(String, String)> = Vec::new();
+
+        if let Some(ref env_file) = self.env_file {
+            let expanded = shellexpand::full(env_file).map_err(|e| {
+                ClaudepodError::Validation(format!("Failed to expand env_file path: {}", e))
+            })?;
+            let path = project_dir.join(expanded.as_ref());
+            let content = fs::read_to_string(&path).map_err(|e| {
+                ClaudepodError::FileNotFound(format!("env_file {}: {}", path.display(), e))
+            })?;
+            for (key, value) in parse_env_file(&content)? {
+                set_var(&mut vars, key, value);
+            }
         }
+
+        for key in &self.pass {
+            if let Some(value) = host_var(key) {
+                set_var(&mut vars, key.clone(), value);
+            }
+        }
+
+        for spec in overrides {
+            match spec.split_once('=') {
+                Some((key, value)) => {
+                    validate_env_key(key)?;
+                    set_var(&mut vars, key.to_string(), value.to_string());
+                }
+                None => {
+                    validate_env_key(spec)?;
+                    if let Some(value) = host_var(spec) {
+                        set_var(&mut vars, spec.clone(), value);
+                    }
+                }
+            }
+        }
+
+        Ok(vars)
+    }
+}
+
+/// Insert or replace a variable, keeping first-seen order
+fn set_var(vars: &mut Vec<(String, String)>, key: String, value: String) {
+    match vars.iter_mut().find(|(k, _)| *k == key) {
+        Some(existing) => existing.1 = value,
+        None => vars.push((key, value)),
+    }
+}
+
+fn validate_env_key(key: &str) -> Result<()> {
+    let valid = !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(ClaudepodError::Validation(format!(
+            "Invalid environment variable name '{}'",
+            key
+        )));
     }
+    Ok(())
+}
+
+/// Parse a dotenv-style file: `KEY=VALUE` lines, optional `export ` prefix,
+/// `#` comments and blank lines, values optionally wrapped in matching quotes.
+pub fn parse_env_file(content: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+
+    for (lineno, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+
+        let (key, value) = line.split_once('=').ok_or_else(|| {
+            ClaudepodError::Validation(format!("env_file line {}: expected KEY=VALUE", lineno + 1))
+        })?;
+        let key = key.trim();
+        validate_env_key(key)?;
+
+        let value = value.trim();
+        let value = if value.len() >= 2
+            && ((value.starts_with('"') && value.ends_with('"'))
+                || (value.starts_with('\'') && value.ends_with('\'')))
+        {
+            &value[1..value.len() - 1]
+        } else {
+            value
+        };
+
+        vars.push((key.to_string(), value.to_string()));
+    }
+
+    Ok(vars)
 }
 
 impl CommandsConfig {
-    /// Resolve a command by name, following references recursively
-    /// Returns (resolved_executable, resolved_config)
-    pub fn resolve<'a>(&'a self, name: &str) -> Result<(String, &'a CommandConfig)> {
+    /// Split `claudepod [ARGS...]` into a command name and its arguments. A
+    /// leading declared command is run with the rest, anything else is passed
+    /// to the default command.
+    pub fn dispatch(&self, args: &[String]) -> (String, Vec<String>) {
+        match args.split_first() {
+            Some((first, rest)) if self.commands.contains_key(first.as_str()) => {
+                (first.clone(), rest.to_vec())
+            }
+            _ => (self.default.clone(), args.to_vec()),
+        }
+    }
+
+    /// Resolve `name` followed by `args` for execution. Commands declared in
+    /// [cmd] are resolved through their aliases: args accumulate (the referenced
+    /// command's first), other settings of an alias override its target's.
+    /// Anything else runs as-is.
+    pub fn resolve_command(&self, name: &str, args: &[String]) -> Result<ResolvedCommand> {
+        if !self.commands.contains_key(name) {
+            let mut argv = vec![name.to_string()];
+            argv.extend(args.iter().cloned());
+            return Ok(ResolvedCommand {
+                argv,
+                ..Default::default()
+            });
+        }
+
+        let (executable, chain) = self.resolve(name)?;
+        let mut resolved = ResolvedCommand {
+            argv: vec![executable],
+            ..Default::default()
+        };
+
+        for config in chain.iter().rev() {
+            resolved
+                .argv
+                .extend(config.args.split_whitespace().map(String::from));
+            resolved.env.extend(config.env.clone());
+            for (target, value) in [
+                (&mut resolved.workdir, &config.workdir),
+                (&mut resolved.user, &config.user),
+                (&mut resolved.pre, &config.pre),
+                (&mut resolved.post, &config.post),
+            ] {
+                if value.is_some() {
+                    target.clone_from(value);
+                }
+            }
+        }
+
+        resolved.argv.extend(args.iter().cloned());
+        Ok(resolved)
+    }
+
+    /// Resolve a command by name, following references recursively. A reference
+    /// to a name not declared in [cmd] is taken as the executable.
+    /// Returns (resolved_executable, configs from `name` down the alias chain)
+    pub fn resolve<'a>(&'a self, name: &str) -> Result<(String, Vec<&'a CommandConfig>)> {
         let mut current_name = name;
         let mut visited = std::collections::HashSet::new();
+        let mut chain = Vec::new();
         const MAX_DEPTH: usize = 10;
 
         for _ in 0..MAX_DEPTH {
@@ -332,16 +1215,24 @@ impl CommandsConfig {
                 )));
             }
 
-            let config = self.commands.get(current_name).ok_or_else(|| {
-                ClaudepodError::Other(format!("Command not found: {}", current_name))
-            })?;
+            let config = match self.commands.get(current_name) {
+                Some(config) => config,
+                None if !chain.is_empty() => return Ok((current_name.to_string(), chain)),
+                None => {
+                    return Err(ClaudepodError::Other(format!(
+                        "Command not found: {}",
+                        current_name
+                    )))
+                }
+            };
+            chain.push(config);
 
             // If this command references another, follow it
             if let Some(ref cmd_ref) = config.command {
                 current_name = cmd_ref;
             } else {
                 // No reference, use current name as executable
-                return Ok((current_name.to_string(), config));
+                return Ok((current_name.to_string(), chain));
             }
         }
 
@@ -365,6 +1256,7 @@ impl Default for CommandsConfig {
                 ),
                 args: "--dangerously-skip-permissions --max-turns 99999999".to_string(),
                 command: None,
+                ..Default::default()
             },
         );
 
@@ -375,6 +1267,7 @@ impl Default for CommandsConfig {
                 install: None,
                 args: String::new(),
                 command: Some("bash".to_string()),
+                ..Default::default()
             },
         );
 
@@ -384,6 +1277,7 @@ impl Default for CommandsConfig {
                 install: None,
                 args: String::new(),
                 command: None,
+                ..Default::default()
             },
         );
 
@@ -393,6 +1287,7 @@ impl Default for CommandsConfig {
                 install: None,
                 args: String::new(),
                 command: None,
+                ..Default::default()
             },
         );
 
@@ -447,7 +1342,7 @@ impl Profile {
     /// Load a profile by name from the profiles directory
     /// e.g., load("default") loads ~/.config/claudepod/profiles/default.toml
     pub fn load(name: &str) -> Result<Self> {
-        let profile_path = paths::profiles_dir().join(format!("{}.toml", name));
+        let profile_path = Self::path(name);
 
         if !profile_path.exists() {
             return Err(ClaudepodError::ProfileNotFound(format!(
@@ -460,6 +1355,27 @@ impl Profile {
         Self::from_file(&profile_path)
     }
 
+    /// Profile names become file names in the profiles directory, so they
+    /// can't contain path separators or point outside it
+    pub fn validate_name(name: &str) -> Result<()> {
+        if name.is_empty()
+            || name.starts_with('.')
+            || name.contains("..")
+            || name.contains(['/', '\\'])
+        {
+            return Err(ClaudepodError::Validation(format!(
+                "Invalid profile name '{}'. Names can't be empty, start with '.' or contain '/' or '..'",
+                name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Path of a named profile in the profiles directory
+    pub fn path(name: &str) -> PathBuf {
+        paths::profiles_dir().join(format!("{}.toml", name))
+    }
+
     /// Load profile from a TOML file
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
         let content = fs::read_to_string(&path).map_err(|e| {
@@ -475,6 +1391,16 @@ impl Profile {
         Ok(profile)
     }
 
+    /// Check the parts of the profile that refer to this host (devices and
+    /// the seccomp file), which `validate` leaves out so profiles stay
+    /// loadable on other hosts
+    pub fn check_host(&self) -> Result<()> {
+        for device in &self.docker.devices {
+            device.check_host()?;
+        }
+        self.security.check_host()
+    }
+
     /// Validate the profile
     pub fn validate(&self) -> Result<()> {
         // Validate container runtime
@@ -487,6 +1413,26 @@ impl Profile {
             )));
         }
 
+        // Validate GPU toggle
+        if let GpuToggle::Mode(ref mode) = self.docker.enable_gpu {
+            if mode != "auto" {
+                return Err(ClaudepodError::Validation(format!(
+                    "Invalid docker.enable_gpu '{}'. Must be true, false or \"auto\"",
+                    mode
+                )));
+            }
+        }
+
+        // Validate workspace mode
+        let valid_workspace_modes = ["bind", "overlay"];
+        if !valid_workspace_modes.contains(&self.docker.workspace_mode.as_str()) {
+            return Err(ClaudepodError::Validation(format!(
+                "Invalid docker.workspace_mode '{}'. Must be one of: {}",
+                self.docker.workspace_mode,
+                valid_workspace_modes.join(", ")
+            )));
+        }
+
         // Validate base image is not empty
         if self.container.base_image.is_empty() {
             return Err(ClaudepodError::Validation(
@@ -510,6 +1456,111 @@ impl Profile {
             }
         }
 
+        // Validate device passthrough
+        for device in &self.docker.devices {
+            device.validate()?;
+        }
+
+        // Validate published ports
+        for (i, port) in self.docker.ports.iter().enumerate() {
+            port.validate()?;
+            let duplicate = self.docker.ports[..i]
+                .iter()
+                .any(|p| p.host != 0 && p.host == port.host && p.protocol == port.protocol);
+            if duplicate {
+                return Err(ClaudepodError::Validation(format!(
+                    "Host port {}/{} is published more than once",
+                    port.host, port.protocol
+                )));
+            }
+        }
+
+        // Validate exec_env passthrough names
+        for key in &self.exec_env.pass {
+            validate_env_key(key)?;
+        }
+
+        // Validate hooks
+        let hooks = &self.hooks;
+        for hook in hooks
+            .on_create
+            .iter()
+            .chain(&hooks.on_start)
+            .chain(&hooks.on_run_exit)
+        {
+            if hook.command.trim().is_empty() {
+                return Err(ClaudepodError::Validation(
+                    "Hook command cannot be empty".to_string(),
+                ));
+            }
+        }
+
+        // Validate session settings
+        if self
+            .session
+            .auto_snapshot
+            .as_ref()
+            .is_some_and(|a| a.keep == 0)
+        {
+            return Err(ClaudepodError::Validation(
+                "session.auto_snapshot.keep must be at least 1".to_string(),
+            ));
+        }
+
+        // Validate per-command settings
+        for (name, command) in &self.cmd.commands {
+            for key in command.env.keys() {
+                validate_env_key(key)?;
+            }
+            if command.user.as_deref() == Some("") || command.workdir.as_deref() == Some("") {
+                return Err(ClaudepodError::Validation(format!(
+                    "Command '{}': user and workdir cannot be empty",
+                    name
+                )));
+            }
+            if let Some(ref workdir) = command.workdir {
+                let path = Path::new(workdir);
+                if path.is_absolute() || path.components().any(|c| c == Component::ParentDir) {
+                    return Err(ClaudepodError::Validation(format!(
+                        "Command '{}': workdir '{}' must be a path inside the project (relative, without '..')",
+                        name, workdir
+                    )));
+                }
+            }
+        }
+
+        // Validate network mode
+        let valid_modes = ["default", "none", "host", "allowlist"];
+        if !valid_modes.contains(&self.network.mode.as_str()) {
+            return Err(ClaudepodError::Validation(format!(
+                "Invalid network mode '{}'. Must be one of: {}",
+                self.network.mode,
+                valid_modes.join(", ")
+            )));
+        }
+        for domain in &self.network.allow {
+            let (pattern, port_ok) = match domain.rsplit_once(':') {
+                Some((pattern, port)) => (pattern, port.parse::<u16>().is_ok_and(|p| p > 0)),
+                None => (domain.as_str(), true),
+            };
+            let host = pattern.strip_prefix("*.").unwrap_or(pattern);
+            if !port_ok || host.is_empty() || host.contains(['/', ':', '*', ' ']) {
+                return Err(ClaudepodError::Validation(format!(
+                    "Invalid network allow entry '{}'. Expected a domain like 'example.com', '*.example.com' or 'example.com:8443'",
+                    domain
+                )));
+            }
+        }
+
+        // Validate resource limits
+        self.resources.validate()?;
+
+        // Validate security options
+        self.security.validate()?;
+
+        // Validate GPU driver injection
+        self.gpu.validate()?;
+
         // Validate nodejs source
         if self.dependencies.nodejs.enabled {
             let valid_sources = ["nodesource", "apt", "nvm"];
@@ -525,19 +1576,43 @@ impl Profile {
         Ok(())
     }
 
-    /// Serialize profile to TOML string (normalized for hashing)
+    /// Serialize profile to TOML string
     pub fn to_toml_string(&self) -> Result<String> {
         Ok(toml::to_string_pretty(self)?)
     }
 
-    /// Compute SHA256 hash of the profile configuration
+    /// Compute SHA256 hash of the profile configuration. Hashed through
+    /// `toml::Value` so map fields serialize with sorted keys.
     pub fn compute_hash(&self) -> Result<String> {
-        let toml_str = self.to_toml_string()?;
+        let toml_str = toml::to_string(&toml::Value::try_from(self)?)?;
         let mut hasher = Sha256::new();
         hasher.update(toml_str.as_bytes());
         Ok(format!("{:x}", hasher.finalize()))
     }
 
+    /// Field-level differences from `self` to `other`, sorted by dotted path.
+    /// Arrays of tables are compared per element (e.g. `docker.volumes[1].host`).
+    pub fn diff(&self, other: &Profile) -> Result<Vec<FieldDiff>> {
+        let mut old = BTreeMap::new();
+        let mut new = BTreeMap::new();
+        flatten_toml("", &toml::Value::try_from(self)?, &mut old);
+        flatten_toml("", &toml::Value::try_from(other)?, &mut new);
+
+        let mut paths: Vec<&String> = old.keys().chain(new.keys()).collect();
+        paths.sort();
+        paths.dedup();
+
+        Ok(paths
+            .into_iter()
+            .filter(|path| old.get(*path) != new.get(*path))
+            .map(|path| FieldDiff {
+                path: path.clone(),
+                old: old.get(path).map(|v| v.to_string()),
+                new: new.get(path).map(|v| v.to_string()),
+            })
+            .collect())
+    }
+
     /// List available profile names (without .toml extension)
     pub fn list_available() -> Result<Vec<String>> {
         let profiles_dir = paths::profiles_dir();
@@ -547,7 +1622,7 @@ impl Profile {
             for entry in fs::read_dir(&profiles_dir)? {
                 let entry = entry?;
                 let path = entry.path();
-                if path.extension().map_or(false, |e| e == "toml") {
+                if path.extension().is_some_and(|e| e == "toml") {
                     if let Some(stem) = path.file_stem() {
                         profiles.push(stem.to_string_lossy().to_string());
                     }
@@ -587,10 +1662,17 @@ impl Profile {
                 env.insert("TERM".to_string(), "xterm-256color".to_string());
                 env
             },
+            exec_env: ExecEnvConfig::default(),
+            network: NetworkConfig::default(),
+            resources: ResourcesConfig::default(),
+            security: SecurityConfig::default(),
+            gpu: GpuConfig::default(),
+            hooks: HooksConfig::default(),
             git: GitConfig::default(),
             cmd: CommandsConfig::default(),
             dependencies: DependenciesConfig::default(),
             shell: ShellConfig::default(),
+            session: SessionConfig::default(),
         }
     }
 }
@@ -615,11 +1697,67 @@ mod tests {
         assert_eq!(profile.container.user, parsed.container.user);
     }
 
+    #[test]
+    fn test_validate_name() {
+        assert!(Profile::validate_name("default").is_ok());
+        assert!(Profile::validate_name("gpu-dev_2").is_ok());
+        assert!(Profile::validate_name("").is_err());
+        assert!(Profile::validate_name("../evil").is_err());
+        assert!(Profile::validate_name("a/b").is_err());
+        assert!(Profile::validate_name("a..b").is_err());
+        assert!(Profile::validate_name(".hidden").is_err());
+    }
+
     #[test]
     fn test_profile_hash() {
         let profile = Profile::default();
         let hash = profile.compute_hash().unwrap();
         assert_eq!(hash.len(), 64); // SHA256 hex string
+
+        // Stable across separately built maps (environment, commands, aliases)
+        let toml_str = profile.to_toml_string().unwrap();
+        for _ in 0..8 {
+            let parsed = Profile::from_str(&toml_str).unwrap();
+            assert_eq!(parsed.compute_hash().unwrap(), hash);
+        }
+    }
+
+    #[test]
+    fn test_session_config() {
+        let profile = Profile::default();
+        assert!(profile.session.auto_snapshot.is_none());
+        assert!(!profile.session.git_checkpoint);
+
+        let profile = Profile::from_str("[session]\nauto_snapshot = {}\n").unwrap();
+        assert_eq!(profile.session.auto_snapshot.unwrap().keep, 5);
+
+        let profile = Profile::from_str("[session]\nauto_snapshot = { keep = 2 }\n").unwrap();
+        assert_eq!(profile.session.auto_snapshot.unwrap().keep, 2);
+        assert!(!profile.session.git_checkpoint);
+
+        let profile = Profile::from_str("[session]\ngit_checkpoint = true\n").unwrap();
+        assert!(profile.session.git_checkpoint);
+
+        // Shown with explicit values, and read back the same
+        let toml_str = Profile::default().to_toml_string().unwrap();
+        assert!(toml_str.contains("[session]\ngit_checkpoint = false\n"));
+        let mut profile = Profile::default();
+        profile.session.auto_snapshot = Some(AutoSnapshotConfig { keep: 3 });
+        let parsed = Profile::from_str(&profile.to_toml_string().unwrap()).unwrap();
+        assert_eq!(parsed.session, profile.session);
+
+        assert!(Profile::from_str("[session]\nauto_snapshot = { keep = 0 }\n").is_err());
+    }
+
+    #[test]
+    fn test_workspace_mode() {
+        let profile = Profile::default();
+        assert_eq!(profile.docker.workspace_mode, "bind");
+
+        let profile = Profile::from_str("[docker]\nworkspace_mode = \"overlay\"\n").unwrap();
+        assert_eq!(profile.docker.workspace_mode, "overlay");
+
+        assert!(Profile::from_str("[docker]\nworkspace_mode = \"fuse\"\n").is_err());
     }
 
     #[test]
@@ -634,4 +1772,582 @@ mod tests {
         let (exec, _) = profile.cmd.resolve("shell").unwrap();
         assert_eq!(exec, "bash");
     }
+
+    #[test]
+    fn test_dispatch() {
+        let mut cmd = Profile::default().cmd;
+        cmd.default = "zsh".to_string();
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        // No arguments: the configured default, not claude
+        assert_eq!(cmd.dispatch(&[]), ("zsh".to_string(), vec![]));
+
+        // Declared command or alias
+        assert_eq!(
+            cmd.dispatch(&args(&["claude", "--resume"])),
+            ("claude".to_string(), args(&["--resume"]))
+        );
+        let (name, rest) = cmd.dispatch(&args(&["shell"]));
+        assert_eq!(
+            cmd.resolve_command(&name, &rest).unwrap().argv,
+            vec!["bash"]
+        );
+
+        // Anything else is passed through to the default
+        assert_eq!(
+            cmd.dispatch(&args(&["-c", "echo hi"])),
+            ("zsh".to_string(), args(&["-c", "echo hi"]))
+        );
+        assert_eq!(
+            cmd.dispatch(&args(&["python", "x.py"])),
+            ("zsh".to_string(), args(&["python", "x.py"]))
+        );
+    }
+
+    #[test]
+    fn test_resolve_command() {
+        let profile = Profile::default();
+        let args = vec!["-c".to_string(), "print('hello')".to_string()];
+
+        // Declared command with configured args
+        let argv = profile.cmd.resolve_command("claude", &[]).unwrap().argv;
+        assert_eq!(
+            argv,
+            vec![
+                "claude",
+                "--dangerously-skip-permissions",
+                "--max-turns",
+                "99999999"
+            ]
+        );
+
+        // Alias
+        let argv = profile.cmd.resolve_command("shell", &[]).unwrap().argv;
+        assert_eq!(argv, vec!["bash"]);
+
+        // Undeclared program runs as-is
+        let resolved = profile.cmd.resolve_command("python", &args).unwrap();
+        assert_eq!(resolved.argv, vec!["python", "-c", "print('hello')"]);
+        assert_eq!(resolved.workdir, None);
+
+        // Cycles are still an error
+        let mut cmd = profile.cmd.clone();
+        for (name, target) in [("a", "b"), ("b", "a")] {
+            cmd.commands.insert(
+                name.to_string(),
+                CommandConfig {
+                    command: Some(target.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+        assert!(cmd.resolve_command("a", &[]).is_err());
+    }
+
+    #[test]
+    fn test_resolve_command_settings() {
+        let cmd: CommandsConfig = toml::from_str(
+            r#"
+            [cargo]
+            env = { RUST_BACKTRACE = "0", CARGO_TERM_COLOR = "always" }
+            user = "code"
+
+            [test]
+            command = "cargo"
+            args = "test"
+            env = { RUST_BACKTRACE = "1" }
+            workdir = "build"
+            pre = "mkdir -p build"
+            post = "echo done"
+
+            [fix-perms]
+            command = "chown"
+            args = "-R code:code ."
+            user = "root"
+            "#,
+        )
+        .unwrap();
+
+        let resolved = cmd
+            .resolve_command("test", &["--release".to_string()])
+            .unwrap();
+        assert_eq!(resolved.argv, vec!["cargo", "test", "--release"]);
+        assert_eq!(resolved.env["RUST_BACKTRACE"], "1");
+        assert_eq!(resolved.env["CARGO_TERM_COLOR"], "always");
+        assert_eq!(resolved.workdir.as_deref(), Some("build"));
+        assert_eq!(resolved.user.as_deref(), Some("code"));
+        assert_eq!(resolved.pre.as_deref(), Some("mkdir -p build"));
+        assert_eq!(resolved.post.as_deref(), Some("echo done"));
+
+        // An alias to an undeclared program runs that program
+        let resolved = cmd.resolve_command("fix-perms", &[]).unwrap();
+        assert_eq!(resolved.argv, vec!["chown", "-R", "code:code", "."]);
+        assert_eq!(resolved.user.as_deref(), Some("root"));
+    }
+
+    #[test]
+    fn test_parse_env_file() {
+        let content = "# comment\n\nexport FOO=bar\nQUOTED=\"a b\"\nSINGLE='x=y'\nEMPTY=\n";
+        let vars = parse_env_file(content).unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                ("FOO".to_string(), "bar".to_string()),
+                ("QUOTED".to_string(), "a b".to_string()),
+                ("SINGLE".to_string(), "x=y".to_string()),
+                ("EMPTY".to_string(), String::new()),
+            ]
+        );
+
+        assert!(parse_env_file("NOT A VAR").is_err());
+        assert!(parse_env_file("1BAD=x").is_err());
+    }
+
+    #[test]
+    fn test_exec_env_resolve_precedence() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join(".env"),
+            "CLAUDEPOD_TEST_FILE=file\nCLAUDEPOD_TEST_PASS=file\n",
+        )
+        .unwrap();
+        let host = |key: &str| match key {
+            "CLAUDEPOD_TEST_PASS" => Some("host".to_string()),
+            "CLAUDEPOD_TEST_BARE" => Some("bare".to_string()),
+            _ => None,
+        };
+
+        let exec_env = ExecEnvConfig {
+            pass: vec![
+                "CLAUDEPOD_TEST_PASS".to_string(),
+                "CLAUDEPOD_TEST_UNSET".to_string(),
+            ],
+            env_file: Some(".env".to_string()),
+        };
+
+        let vars = exec_env
+            .resolve(
+                temp_dir.path(),
+                &[
+                    "CLAUDEPOD_TEST_FILE=cli".to_string(),
+                    "CLAUDEPOD_TEST_BARE".to_string(),
+                ],
+                host,
+            )
+            .unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                ("CLAUDEPOD_TEST_FILE".to_string(), "cli".to_string()),
+                ("CLAUDEPOD_TEST_PASS".to_string(), "host".to_string()),
+                ("CLAUDEPOD_TEST_BARE".to_string(), "bare".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exec_env_missing_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let exec_env = ExecEnvConfig {
+            pass: vec![],
+            env_file: Some("missing.env".to_string()),
+        };
+        assert!(exec_env.resolve(temp_dir.path(), &[], |_| None).is_err());
+    }
+
+    #[test]
+    fn test_command_workdir_validation() {
+        let mut profile = Profile::default();
+        let workdir = |profile: &mut Profile, dir: &str| {
+            profile
+                .cmd
+                .commands
+                .entry("test".to_string())
+                .or_default()
+                .workdir = Some(dir.to_string());
+        };
+
+        workdir(&mut profile, "build/out");
+        assert!(profile.validate().is_ok());
+        workdir(&mut profile, "/etc");
+        assert!(profile.validate().is_err());
+        workdir(&mut profile, "../other-project");
+        assert!(profile.validate().is_err());
+        workdir(&mut profile, "build/../../other-project");
+        assert!(profile.validate().is_err());
+        workdir(&mut profile, "");
+        assert!(profile.validate().is_err());
+    }
+
+    #[test]
+    fn test_exec_env_invalid_pass_name() {
+        let mut profile = Profile::default();
+        profile.exec_env.pass.push("BAD-NAME".to_string());
+        assert!(profile.validate().is_err());
+    }
+
+    #[test]
+    fn test_network_validation() {
+        let mut profile = Profile::default();
+        assert_eq!(profile.network.mode, "default");
+
+        profile.network.mode = "allowlist".to_string();
+        profile.network.allow = vec!["api.anthropic.com".to_string(), "*.npmjs.org".to_string()];
+        assert!(profile.validate().is_ok());
+
+        profile
+            .network
+            .allow
+            .push("git.example.com:2222".to_string());
+        assert!(profile.validate().is_ok());
+        profile
+            .network
+            .allow
+            .push("git.example.com:ssh".to_string());
+        assert!(profile.validate().is_err());
+        profile.network.allow.truncate(2);
+
+        profile
+            .network
+            .allow
+            .push("https://example.com".to_string());
+        assert!(profile.validate().is_err());
+
+        profile.network.allow.pop();
+        profile.network.mode = "bridge".to_string();
+        assert!(profile.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024").unwrap(), 1024);
+        assert_eq!(parse_size("512m").unwrap(), 512 * 1024 * 1024);
+        assert_eq!(parse_size("2G").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("64k").unwrap(), 64 * 1024);
+        assert_eq!(parse_size("10b").unwrap(), 10);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("m").is_err());
+        assert!(parse_size("1.5g").is_err());
+        assert!(parse_size("8gb").is_err());
+        assert!(parse_size("-1").is_err());
+    }
+
+    #[test]
+    fn test_resources_validation() {
+        let toml_str = r#"
+[resources]
+cpus = 2.5
+memory = "8g"
+memory_swap = "12g"
+pids_limit = 4096
+shm_size = "1g"
+
+[resources.ulimits]
+nofile = "1024:4096"
+core = "unlimited"
+"#;
+        let profile = Profile::from_str(toml_str).unwrap();
+        assert_eq!(profile.resources.cpus, Some(2.5));
+        assert_eq!(profile.resources.ulimits.len(), 2);
+
+        let mut resources = profile.resources.clone();
+        resources.memory = Some("8 gigs".to_string());
+        assert!(resources.validate().is_err());
+
+        let mut resources = profile.resources.clone();
+        resources.memory_swap = Some("4g".to_string());
+        assert!(resources.validate().is_err());
+
+        let mut resources = profile.resources.clone();
+        resources.memory = None;
+        assert!(resources.validate().is_err());
+
+        let mut resources = profile.resources.clone();
+        resources.memory_swap = Some("-1".to_string());
+        assert!(resources.validate().is_ok());
+
+        let mut resources = profile.resources.clone();
+        resources.cpus = Some(0.0);
+        assert!(resources.validate().is_err());
+
+        let mut resources = profile.resources.clone();
+        resources.pids_limit = Some(0);
+        assert!(resources.validate().is_err());
+
+        let mut resources = profile.resources.clone();
+        resources
+            .ulimits
+            .insert("files".to_string(), "10".to_string());
+        assert!(resources.validate().is_err());
+
+        let mut resources = profile.resources;
+        resources
+            .ulimits
+            .insert("nproc".to_string(), "lots".to_string());
+        assert!(resources.validate().is_err());
+    }
+
+    #[test]
+    fn test_security_validation() {
+        let mut profile = Profile::default();
+        assert!(profile.security.sudo);
+
+        profile.security.cap_drop = vec!["ALL".to_string()];
+        profile.security.cap_add = vec!["CAP_CHOWN".to_string(), "NET_BIND_SERVICE".to_string()];
+        assert!(profile.validate().is_ok());
+
+        profile.security.cap_add.push("net-raw".to_string());
+        assert!(profile.validate().is_err());
+        profile.security.cap_add.pop();
+
+        profile.security.seccomp = Some("unconfined".to_string());
+        assert!(profile.validate().is_ok());
+
+        assert!(profile.check_host().is_ok());
+
+        // A missing seccomp file only fails the host check
+        profile.security.seccomp = Some("/nonexistent/seccomp.json".to_string());
+        assert!(profile.validate().is_ok());
+        assert!(profile.check_host().is_err());
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let seccomp_path = temp_dir.path().join("seccomp.json");
+        fs::write(&seccomp_path, "{}").unwrap();
+        profile.security.seccomp = Some(seccomp_path.to_string_lossy().to_string());
+        assert!(profile.check_host().is_ok());
+    }
+
+    #[test]
+    fn test_resolve_host_drivers() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let lib_dir = temp_dir.path().join("lib");
+        fs::create_dir_all(lib_dir.join("libnvoptix.so.subdir")).unwrap();
+        fs::write(lib_dir.join("libnvoptix.so.550.54"), "").unwrap();
+        fs::write(lib_dir.join("libnvidia-rtcore.so.550.54"), "").unwrap();
+        fs::write(lib_dir.join("libcuda.so.1"), "").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("libnvoptix.so.550.54", lib_dir.join("libnvoptix.so.1"))
+            .unwrap();
+
+        let gpu = GpuConfig {
+            host_drivers: "mount".to_string(),
+            host_driver_globs: vec![
+                format!("{}/libnvoptix.so.*", lib_dir.display()),
+                format!("{}/libnvidia-rtcore.*", lib_dir.display()),
+                format!("{}/libnvoptix.so.550.*", lib_dir.display()),
+                format!("{}/missing.*", lib_dir.display()),
+            ],
+            ..Default::default()
+        };
+        assert!(gpu.validate().is_ok());
+
+        let files = gpu.resolve_host_drivers().unwrap();
+        let mut expected = vec![
+            lib_dir.join("libnvidia-rtcore.so.550.54"),
+            lib_dir.join("libnvoptix.so.550.54"),
+        ];
+        #[cfg(unix)]
+        expected.insert(1, lib_dir.join("libnvoptix.so.1"));
+        assert_eq!(files, expected);
+    }
+
+    #[test]
+    fn test_gpu_validation() {
+        let mut gpu = GpuConfig::default();
+        assert_eq!(gpu.host_drivers, "none");
+        assert!(gpu.validate().is_ok());
+
+        gpu.host_drivers = "bind".to_string();
+        assert!(gpu.validate().is_err());
+
+        gpu.host_drivers = "copy".to_string();
+        gpu.host_driver_globs = vec!["relative/lib*.so".to_string()];
+        assert!(gpu.validate().is_err());
+
+        gpu.host_driver_globs = vec!["/usr/lib/[".to_string()];
+        assert!(gpu.validate().is_err());
+
+        let mut gpu = GpuConfig {
+            devices: vec!["0".to_string(), "GPU-1234".to_string()],
+            ..Default::default()
+        };
+        assert!(gpu.validate().is_ok());
+
+        gpu.vendor = "amd".to_string();
+        assert!(gpu.validate().is_err());
+        gpu.devices = vec!["/dev/dri/renderD128".to_string()];
+        assert!(gpu.validate().is_ok());
+
+        gpu.vendor = "matrox".to_string();
+        assert!(gpu.validate().is_err());
+    }
+
+    #[test]
+    fn test_device_validation() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let host = temp_dir.path().join("ttyUSB0");
+        fs::write(&host, "").unwrap();
+
+        let mut device = DeviceMount {
+            host: host.to_string_lossy().to_string(),
+            container: None,
+            permissions: "rw".to_string(),
+        };
+        assert!(device.validate().is_ok());
+        assert_eq!(device.container_path(), device.host);
+
+        device.permissions = "rwx".to_string();
+        assert!(device.validate().is_err());
+        device.permissions = "rr".to_string();
+        assert!(device.validate().is_err());
+        device.permissions = String::new();
+        assert!(device.validate().is_err());
+
+        device.permissions = "rwm".to_string();
+        device.container = Some("dev/ttyUSB0".to_string());
+        assert!(device.validate().is_err());
+
+        device.container = None;
+        assert!(device.check_host().is_ok());
+        device.host = temp_dir
+            .path()
+            .join("missing")
+            .to_string_lossy()
+            .to_string();
+        assert!(device.validate().is_ok());
+        assert!(device.check_host().is_err());
+
+        // A device missing on this host still loads; creating the container fails
+        let profile =
+            Profile::from_str("[[docker.devices]]\nhost = \"/dev/claudepod-missing\"\n").unwrap();
+        assert_eq!(profile.docker.devices[0].permissions, "rwm");
+        assert!(profile.check_host().is_err());
+    }
+
+    #[test]
+    fn test_port_mapping() {
+        let port = PortMapping::parse("3000").unwrap();
+        assert_eq!((port.host, port.container), (3000, 3000));
+        assert_eq!(port.protocol, "tcp");
+        assert_eq!(port.address, "127.0.0.1");
+
+        let port = PortMapping::parse("8080:3000").unwrap();
+        assert_eq!((port.host, port.container), (8080, 3000));
+
+        let port = PortMapping::parse("0:5353/udp").unwrap();
+        assert_eq!((port.host, port.container), (0, 5353));
+        assert_eq!(port.protocol, "udp");
+
+        for spec in ["", "abc", "3000:", "70000", "3000:0", "3000/sctp"] {
+            assert!(PortMapping::parse(spec).is_err(), "{}", spec);
+        }
+
+        let mut profile = Profile::default();
+        profile.docker.ports = vec![
+            PortMapping::parse("3000").unwrap(),
+            PortMapping::parse("3000/udp").unwrap(),
+            PortMapping::parse("0:8000").unwrap(),
+            PortMapping::parse("0:8001").unwrap(),
+        ];
+        assert!(profile.validate().is_ok());
+
+        profile
+            .docker
+            .ports
+            .push(PortMapping::parse("3000:4000").unwrap());
+        assert!(profile.validate().is_err());
+
+        profile.docker.ports.pop();
+        profile.docker.ports[0].address = "localhost".to_string();
+        assert!(profile.validate().is_err());
+    }
+
+    #[test]
+    fn test_profile_diff() {
+        let old = Profile::default();
+        assert!(old.diff(&old.clone()).unwrap().is_empty());
+
+        let mut new = old.clone();
+        new.container.base_image = "ubuntu:24.04".to_string();
+        new.docker.volumes.remove(2);
+        new.environment.insert("DEBUG".to_string(), "1".to_string());
+        new.dependencies.apt.push("htop".to_string());
+
+        let diff = old.diff(&new).unwrap();
+        let find = |path: &str| diff.iter().find(|d| d.path == path);
+
+        let base_image = find("container.base_image").unwrap();
+        assert_eq!(base_image.old.as_deref(), Some("\"ubuntu:25.04\""));
+        assert_eq!(base_image.new.as_deref(), Some("\"ubuntu:24.04\""));
+
+        let removed = find("docker.volumes[2].host").unwrap();
+        assert_eq!(removed.old.as_deref(), Some("\"$HOME/.claude.json\""));
+        assert_eq!(removed.new, None);
+        assert!(find("docker.volumes[0].host").is_none());
+
+        let added = find("environment.DEBUG").unwrap();
+        assert_eq!(
+            (added.old.as_deref(), added.new.as_deref()),
+            (None, Some("\"1\""))
+        );
+
+        // Arrays of plain values are compared as a whole
+        assert!(find("dependencies.apt").is_some());
+
+        // Sorted by path
+        let paths: Vec<_> = diff.iter().map(|d| d.path.clone()).collect();
+        let mut sorted = paths.clone();
+        sorted.sort();
+        assert_eq!(paths, sorted);
+    }
+
+    #[test]
+    fn test_field_diff_requires_rebuild() {
+        let field = |path: &str| FieldDiff {
+            path: path.to_string(),
+            old: None,
+            new: None,
+        };
+
+        for path in [
+            "container.base_image",
+            "environment.DEBUG",
+            "dependencies.apt",
+            "dependencies.custom[0].name",
+            "security.sudo",
+            "cmd.commands.claude.install",
+        ] {
+            assert!(field(path).requires_rebuild(), "{}", path);
+        }
+        for path in [
+            "docker.volumes[0].host",
+            "security.cap_drop",
+            "gpu.vendor",
+            "cmd.commands.claude.command",
+            "cmd.default",
+            "environmental",
+        ] {
+            assert!(!field(path).requires_rebuild(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_enable_gpu_values() {
+        for (value, expected) in [
+            ("true", GpuToggle::Enabled(true)),
+            ("false", GpuToggle::Enabled(false)),
+            ("\"auto\"", GpuToggle::default()),
+        ] {
+            let profile: Profile =
+                toml::from_str(&format!("[docker]\nenable_gpu = {}\n", value)).unwrap();
+            assert_eq!(profile.docker.enable_gpu, expected);
+            assert!(profile.validate().is_ok());
+        }
+
+        let profile: Profile = toml::from_str("[docker]\nenable_gpu = \"yes\"\n").unwrap();
+        assert!(profile.validate().is_err());
+
+        // Omitted means auto
+        assert_eq!(Profile::default().docker.enable_gpu, GpuToggle::default());
+    }
 }
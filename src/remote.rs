@@ -0,0 +1,78 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Quote a word for a POSIX shell (the remote side of ssh runs one)
+pub fn shell_quote(word: &str) -> String {
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@+,".contains(c))
+    {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+/// Remote project directory for a local one: the same path relative to the
+/// home directory (ssh starts there), or the absolute path outside of it
+pub fn default_dir(project_dir: &Path) -> String {
+    let relative = dirs::home_dir().and_then(|home| {
+        project_dir
+            .strip_prefix(home)
+            .ok()
+            .map(|p| p.to_string_lossy().into_owned())
+    });
+    match relative {
+        Some(relative) if relative.is_empty() => ".".to_string(),
+        Some(relative) => relative,
+        None => project_dir.to_string_lossy().into_owned(),
+    }
+}
+
+/// Shell command that runs claudepod with `args` inside `dir`
+pub fn command_line(dir: &str, claudepod: &str, args: &[&str]) -> String {
+    let mut line = format!("cd {} && {}", shell_quote(dir), shell_quote(claudepod));
+    for arg in args {
+        line.push(' ');
+        line.push_str(&shell_quote(arg));
+    }
+    line
+}
+
+/// `ssh <host> <command>`; stdin/stdout carry the bundle
+pub fn ssh(host: &str, command: &str) -> Command {
+    let mut cmd = Command::new("ssh");
+    cmd.args(["-o", "BatchMode=yes", "--", host, command]);
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("load"), "load");
+        assert_eq!(shell_quote("code/my-project"), "code/my-project");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("my dir"), "'my dir'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote("$(rm -rf ~)"), "'$(rm -rf ~)'");
+    }
+
+    #[test]
+    fn test_command_line() {
+        assert_eq!(
+            command_line("code/my project", "claudepod", &["-c", "main", "load", "-"]),
+            "cd 'code/my project' && claudepod -c main load -"
+        );
+    }
+
+    #[test]
+    fn test_default_dir() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(default_dir(&home.join("code/project")), "code/project");
+        assert_eq!(default_dir(&home), ".");
+        assert_eq!(default_dir(Path::new("/srv/project")), "/srv/project");
+    }
+}
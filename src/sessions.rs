@@ -0,0 +1,351 @@
+use chrono::Utc;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::error::{ClaudepodError, Result};
+
+/// Namespace of the session refs, one per session
+pub const SESSIONS_REF: &str = "refs/claudepod/sessions";
+
+/// A checkpointed agent session: `start` is a commit of the project before
+/// it, `end` after it (`None` if claudepod did not see the session finish)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub id: String,
+    pub start: String,
+    pub end: Option<String>,
+}
+
+fn session_ref(id: &str) -> String {
+    format!("{}/{}", SESSIONS_REF, id)
+}
+
+/// `git -C dir`, committing as claudepod (checkpoints must not depend on
+/// the user's identity being configured)
+fn git_command(dir: &Path) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C")
+        .arg(dir)
+        .env("GIT_AUTHOR_NAME", "claudepod")
+        .env("GIT_AUTHOR_EMAIL", "claudepod@localhost")
+        .env("GIT_COMMITTER_NAME", "claudepod")
+        .env("GIT_COMMITTER_EMAIL", "claudepod@localhost");
+    cmd
+}
+
+/// Run a git command, returning its raw stdout
+fn run(cmd: &mut Command, args: &[&str]) -> Result<Vec<u8>> {
+    let output = cmd
+        .args(args)
+        .output()
+        .map_err(|e| ClaudepodError::Git(format!("Failed to run git: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(ClaudepodError::Git(format!(
+            "git {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Run git in `dir`, returning its stdout without the trailing newline
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let stdout = run(&mut git_command(dir), args)?;
+    Ok(String::from_utf8_lossy(&stdout).trim_end().to_string())
+}
+
+/// Whether `dir` is inside a git working tree
+pub fn is_repo(dir: &Path) -> bool {
+    git(dir, &["rev-parse", "--is-inside-work-tree"]).is_ok_and(|out| out == "true")
+}
+
+/// Commit the project's part of the working tree as it is (tracked and
+/// untracked files, without ignored ones) through a temporary index, leaving
+/// the real index, HEAD and branches alone. The project may be a
+/// subdirectory of the repository; the rest of it is committed as staged.
+fn commit_worktree(dir: &Path, message: &str, parent: Option<&str>) -> Result<String> {
+    let index = dir.join(git(dir, &["rev-parse", "--git-path", "claudepod-index"])?);
+    let real_index = dir.join(git(dir, &["rev-parse", "--git-path", "index"])?);
+
+    // Starting from a copy of the real index reuses its stat cache
+    if real_index.exists() {
+        fs::copy(&real_index, &index)?;
+    }
+
+    let commit = (|| {
+        let with_index = |args: &[&str]| -> Result<String> {
+            let stdout = run(git_command(dir).env("GIT_INDEX_FILE", &index), args)?;
+            Ok(String::from_utf8_lossy(&stdout).trim_end().to_string())
+        };
+        with_index(&["add", "--all", "--", "."])?;
+        let tree = with_index(&["write-tree"])?;
+
+        let mut args = vec!["commit-tree", tree.as_str(), "-m", message];
+        if let Some(parent) = parent {
+            args.extend(["-p", parent]);
+        }
+        git(dir, &args)
+    })();
+
+    let _ = fs::remove_file(&index);
+    commit
+}
+
+/// Checkpoint the project before a session. Returns the session id (the
+/// start time, e.g. 20250101-120000).
+pub fn begin(dir: &Path) -> Result<String> {
+    let stamp = Utc::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut id = stamp.clone();
+    for n in 2.. {
+        if git(
+            dir,
+            &["rev-parse", "--verify", "--quiet", &session_ref(&id)],
+        )
+        .is_err()
+        {
+            break;
+        }
+        id = format!("{}-{}", stamp, n);
+    }
+
+    let head = git(dir, &["rev-parse", "--verify", "--quiet", "HEAD"]).ok();
+    let start = commit_worktree(
+        dir,
+        &format!("claudepod: before session {}", id),
+        head.as_deref(),
+    )?;
+    git(dir, &["update-ref", &session_ref(&id), &start])?;
+    Ok(id)
+}
+
+/// Checkpoint the project after a session. The session ref then points at
+/// the end state, whose parent is the start state.
+pub fn finish(dir: &Path, id: &str) -> Result<()> {
+    let reference = session_ref(id);
+    let start = git(dir, &["rev-parse", "--verify", &reference])?;
+    let end = commit_worktree(
+        dir,
+        &format!("claudepod: after session {}", id),
+        Some(&start),
+    )?;
+    git(dir, &["update-ref", &reference, &end, &start])?;
+    Ok(())
+}
+
+/// Checkpointed sessions, newest first
+pub fn list(dir: &Path) -> Result<Vec<Session>> {
+    let refs = git(
+        dir,
+        &[
+            "for-each-ref",
+            "--sort=-refname",
+            "--format=%(refname:lstrip=3) %(objectname) %(subject)",
+            SESSIONS_REF,
+        ],
+    )?;
+
+    let mut sessions = Vec::new();
+    for line in refs.lines() {
+        let mut fields = line.splitn(3, ' ');
+        let (Some(id), Some(commit), Some(subject)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let session = if subject.starts_with("claudepod: after session") {
+            Session {
+                id: id.to_string(),
+                start: git(dir, &["rev-parse", &format!("{}^", commit)])?,
+                end: Some(commit.to_string()),
+            }
+        } else {
+            Session {
+                id: id.to_string(),
+                start: commit.to_string(),
+                end: None,
+            }
+        };
+        sessions.push(session);
+    }
+    Ok(sessions)
+}
+
+/// Summary of the changes made during a session, e.g.
+/// "2 files changed, 10 insertions(+), 1 deletion(-)" (empty if none)
+pub fn diffstat(dir: &Path, session: &Session) -> Result<String> {
+    match &session.end {
+        Some(end) => git(
+            dir,
+            &["diff", "--shortstat", &session.start, end, "--", "."],
+        )
+        .map(|stat| stat.trim().to_string()),
+        None => Ok(String::new()),
+    }
+}
+
+/// Undo the changes made during a session in the project's part of the
+/// working tree. Later changes stay; if they touch the same lines, nothing
+/// is changed.
+pub fn revert(dir: &Path, id: &str) -> Result<()> {
+    let session = list(dir)?.into_iter().find(|s| s.id == id).ok_or_else(|| {
+        ClaudepodError::Other(format!(
+            "Session '{}' not found. Run 'claudepod sessions' to list them.",
+            id
+        ))
+    })?;
+    let end = session.end.ok_or_else(|| {
+        ClaudepodError::Other(format!(
+            "Session '{}' did not finish, so its changes are unknown.",
+            id
+        ))
+    })?;
+
+    let patch = run(
+        &mut git_command(dir),
+        &["diff", "--binary", &session.start, &end, "--", "."],
+    )?;
+    if patch.is_empty() {
+        return Err(ClaudepodError::Other(format!(
+            "Session '{}' made no changes.",
+            id
+        )));
+    }
+
+    // Patch paths are relative to the top of the working tree
+    let top = git(dir, &["rev-parse", "--show-toplevel"])?;
+    let mut child = Command::new("git")
+        .args(["-C", &top, "apply", "-R", "--whitespace=nowarn"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| ClaudepodError::Git(format!("Failed to run git: {}", e)))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(&patch)?;
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        return Err(ClaudepodError::Git(format!(
+            "Could not revert session '{}'; later changes conflict with it:\n{}",
+            id,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn repo() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        git(dir.path(), &["init", "--quiet"]).unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+        git(dir.path(), &["add", "--all"]).unwrap();
+        git(dir.path(), &["commit", "--quiet", "-m", "initial"]).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_session_checkpoints() {
+        let dir = repo();
+        let path = dir.path();
+        let head = git(path, &["rev-parse", "HEAD"]).unwrap();
+
+        let id = begin(path).unwrap();
+        assert_eq!(list(path).unwrap()[0].end, None);
+
+        fs::write(path.join("main.rs"), "fn main() { todo!() }\n").unwrap();
+        fs::write(path.join("new.rs"), "// new\n").unwrap();
+        fs::create_dir(path.join("target")).unwrap();
+        fs::write(path.join("target/out"), "ignored").unwrap();
+        finish(path, &id).unwrap();
+
+        let sessions = list(path).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, id);
+        assert!(sessions[0].end.is_some());
+        assert!(diffstat(path, &sessions[0])
+            .unwrap()
+            .starts_with("2 files changed"));
+
+        // The user's HEAD and index are untouched
+        assert_eq!(git(path, &["rev-parse", "HEAD"]).unwrap(), head);
+        assert!(git(path, &["diff", "--cached", "--name-only"])
+            .unwrap()
+            .is_empty());
+
+        revert(path, &id).unwrap();
+        assert_eq!(
+            fs::read_to_string(path.join("main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+        assert!(!path.join("new.rs").exists());
+        assert!(path.join("target/out").exists());
+    }
+
+    #[test]
+    fn test_project_in_subdirectory() {
+        let dir = repo();
+        let top = dir.path();
+        let path = top.join("project");
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("lib.rs"), "// lib\n").unwrap();
+        git(top, &["add", "--all"]).unwrap();
+        git(top, &["commit", "--quiet", "-m", "project"]).unwrap();
+
+        let id = begin(&path).unwrap();
+        fs::write(path.join("lib.rs"), "// changed\n").unwrap();
+        fs::write(top.join("main.rs"), "// outside\n").unwrap();
+        fs::write(top.join("notes.txt"), "untracked\n").unwrap();
+        finish(&path, &id).unwrap();
+
+        // Only the project is checkpointed
+        let session = &list(&path).unwrap()[0];
+        let end = session.end.as_deref().unwrap();
+        assert!(diffstat(&path, session)
+            .unwrap()
+            .starts_with("1 file changed"));
+        let files = git(top, &["ls-tree", "-r", "--name-only", end]).unwrap();
+        assert!(!files.contains("notes.txt"));
+
+        // and only the project is reverted
+        revert(&path, &id).unwrap();
+        assert_eq!(fs::read_to_string(path.join("lib.rs")).unwrap(), "// lib\n");
+        assert_eq!(
+            fs::read_to_string(top.join("main.rs")).unwrap(),
+            "// outside\n"
+        );
+        assert!(top.join("notes.txt").exists());
+    }
+
+    #[test]
+    fn test_revert_conflict() {
+        let dir = repo();
+        let path = dir.path();
+
+        let id = begin(path).unwrap();
+        fs::write(path.join("main.rs"), "fn main() { a() }\n").unwrap();
+        finish(path, &id).unwrap();
+
+        // A later edit of the same line keeps the session from reverting
+        fs::write(path.join("main.rs"), "fn main() { b() }\n").unwrap();
+        assert!(revert(path, &id).is_err());
+        assert_eq!(
+            fs::read_to_string(path.join("main.rs")).unwrap(),
+            "fn main() { b() }\n"
+        );
+
+        assert!(revert(path, "missing").is_err());
+    }
+}
@@ -7,12 +7,16 @@ use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
 use crate::error::{ClaudepodError, Result};
+use crate::migrations;
 use crate::paths;
-use crate::profile::{CommandsConfig, DockerConfig};
+use crate::profile::{FieldDiff, Profile};
 
 /// Index of all tracked projects (~/.claudepod/projects.toml)
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ProjectsIndex {
+    /// Layout version (see `migrations::INDEX_MIGRATIONS`)
+    pub schema_version: u32,
+
     #[serde(default)]
     pub projects: HashMap<String, ProjectEntry>,
 }
@@ -29,6 +33,9 @@ pub struct ProjectEntry {
 /// Per-project data (stored in ~/.claudepod/projects/{id}/project.toml)
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProjectData {
+    /// Layout version (see `migrations::PROJECT_MIGRATIONS`)
+    pub schema_version: u32,
+
     /// Name of the default container
     pub default: String,
 
@@ -50,21 +57,86 @@ pub struct ContainerInfo {
     pub created_at: DateTime<Utc>,
 
     /// The image tag used for this container
-    #[serde(default)]
     pub image_tag: String,
 
-    /// Frozen docker/runtime configuration (volumes, mounts, etc.)
-    #[serde(default)]
-    pub docker: Option<DockerConfig>,
+    /// Layout version (see `migrations::CONTAINER_MIGRATIONS`)
+    pub schema_version: u32,
+
+    /// Hash of the profile the image was built from
+    pub profile_hash: String,
+
+    /// Complete resolved profile, frozen when the container was created
+    pub config: Profile,
+
+    /// Snapshots of the container's state, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub snapshots: Vec<Snapshot>,
+}
+
+/// A named snapshot of a container's state, stored as an image
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Snapshot {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
 
-    /// Frozen command configuration
     #[serde(default)]
-    pub commands: Option<CommandsConfig>,
+    pub description: String,
+
+    /// Image the container state was committed to
+    pub image_tag: String,
+
+    /// Taken before a session (`session.auto_snapshot`), and rotated out
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto: bool,
+}
+
+/// How a container's frozen config differs from its profile
+#[derive(Debug)]
+pub struct Drift {
+    /// Changes baked into the image
+    pub rebuild: Vec<FieldDiff>,
+
+    /// Changes applied when the container is created
+    pub recreate: Vec<FieldDiff>,
+}
+
+impl ContainerInfo {
+    /// Freeze `config` for a new container running `image_tag`
+    pub fn new(uuid: String, profile: &str, image_tag: String, config: Profile) -> Result<Self> {
+        Ok(ContainerInfo {
+            uuid,
+            profile: profile.to_string(),
+            created_at: Utc::now(),
+            image_tag,
+            schema_version: migrations::latest(migrations::CONTAINER_MIGRATIONS),
+            profile_hash: config.compute_hash()?,
+            config,
+            snapshots: Vec::new(),
+        })
+    }
+
+    /// Find a snapshot by name
+    pub fn snapshot(&self, name: &str) -> Option<&Snapshot> {
+        self.snapshots.iter().find(|s| s.name == name)
+    }
+
+    /// Compare the frozen config with `profile`. The image is only out of
+    /// date if something in `rebuild` changed: the profile hash also covers
+    /// runtime settings such as ports and hooks.
+    pub fn drift(&self, profile: &Profile) -> Result<Drift> {
+        let (rebuild, recreate) = self
+            .config
+            .diff(profile)?
+            .into_iter()
+            .partition(|field| field.requires_rebuild());
+        Ok(Drift { rebuild, recreate })
+    }
 }
 
 impl Default for ProjectData {
     fn default() -> Self {
         Self {
+            schema_version: migrations::latest(migrations::PROJECT_MIGRATIONS),
             default: "main".to_string(),
             containers: HashMap::new(),
         }
@@ -73,6 +145,7 @@ impl Default for ProjectData {
 
 impl ProjectData {
     /// Create a new project data with default settings
+    #[allow(dead_code)]
     pub fn new() -> Self {
         Self::default()
     }
@@ -125,6 +198,15 @@ impl ProjectData {
         self.containers.remove(name)
     }
 
+    /// Name of the container holding a snapshot, if any (snapshot names are
+    /// unique within a project, as they name the snapshot image)
+    pub fn snapshot_owner(&self, snapshot: &str) -> Option<&String> {
+        self.containers
+            .iter()
+            .find(|(_, info)| info.snapshot(snapshot).is_some())
+            .map(|(name, _)| name)
+    }
+
     /// Check if a container exists
     #[allow(dead_code)]
     pub fn has_container(&self, name: &str) -> bool {
@@ -139,6 +221,15 @@ impl ProjectData {
     }
 }
 
+impl Default for ProjectsIndex {
+    fn default() -> Self {
+        Self {
+            schema_version: migrations::latest(migrations::INDEX_MIGRATIONS),
+            projects: HashMap::new(),
+        }
+    }
+}
+
 impl ProjectsIndex {
     /// Load the projects index from ~/.claudepod/projects.toml
     pub fn load() -> Result<Self> {
@@ -147,9 +238,7 @@ impl ProjectsIndex {
             return Ok(Self::default());
         }
 
-        let content = fs::read_to_string(&index_path)?;
-        let index: ProjectsIndex = toml::from_str(&content)?;
-        Ok(index)
+        migrations::load_file(&index_path, migrations::INDEX_MIGRATIONS)
     }
 
     /// Save the projects index to ~/.claudepod/projects.toml
@@ -161,6 +250,7 @@ impl ProjectsIndex {
     }
 
     /// Get a project entry by ID
+    #[allow(dead_code)]
     pub fn get(&self, id: &str) -> Option<&ProjectEntry> {
         self.projects.get(id)
     }
@@ -215,7 +305,7 @@ impl ProjectsIndex {
     /// List all projects sorted by last accessed (most recent first)
     pub fn list_by_last_accessed(&self) -> Vec<(&String, &ProjectEntry)> {
         let mut entries: Vec<_> = self.projects.iter().collect();
-        entries.sort_by(|a, b| b.1.last_accessed.cmp(&a.1.last_accessed));
+        entries.sort_by_key(|e| std::cmp::Reverse(e.1.last_accessed));
         entries
     }
 
@@ -253,9 +343,7 @@ pub fn load_project_data(project_id: &str) -> Result<ProjectData> {
         return Ok(ProjectData::default());
     }
 
-    let content = fs::read_to_string(&project_path)?;
-    let data: ProjectData = toml::from_str(&content)?;
-    Ok(data)
+    migrations::load_file(&project_path, migrations::PROJECT_MIGRATIONS)
 }
 
 /// Save project data to ~/.claudepod/projects/{id}/project.toml
@@ -285,6 +373,31 @@ pub fn container_name(uuid: &str) -> String {
     format!("claudepod-{}", short_uuid)
 }
 
+/// Image tag of a snapshot: claudepod:snap-{project_id}-{name}
+pub fn snapshot_tag(project_id: &str, name: &str) -> String {
+    format!("claudepod:snap-{}-{}", project_id, name)
+}
+
+/// Snapshot names become part of an image tag: lowercase letters, digits,
+/// '.', '_' and '-', starting with a letter or digit
+pub fn validate_snapshot_name(name: &str) -> Result<()> {
+    let valid = name.len() <= 64
+        && name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-".contains(c));
+    if !valid {
+        return Err(ClaudepodError::Other(format!(
+            "Invalid snapshot name '{}'. Use up to 64 lowercase letters, digits, '.', '_' and '-'",
+            name
+        )));
+    }
+    Ok(())
+}
+
 /// Generate a new UUID
 pub fn generate_uuid() -> String {
     Uuid::new_v4().to_string()
@@ -293,6 +406,17 @@ pub fn generate_uuid() -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::profile::PortMapping;
+
+    fn test_info(uuid: &str) -> ContainerInfo {
+        ContainerInfo::new(
+            uuid.to_string(),
+            "default",
+            "claudepod:test".to_string(),
+            Profile::default(),
+        )
+        .unwrap()
+    }
 
     #[test]
     fn test_new_project_data() {
@@ -305,14 +429,7 @@ mod tests {
     fn test_add_and_get_container() {
         let mut data = ProjectData::new();
 
-        let info = ContainerInfo {
-            uuid: "test-uuid-1234".to_string(),
-            profile: "default".to_string(),
-            created_at: Utc::now(),
-            image_tag: "claudepod:test".to_string(),
-            docker: None,
-            commands: None,
-        };
+        let info = test_info("test-uuid-1234");
 
         data.add_container("main", info.clone());
 
@@ -327,6 +444,56 @@ mod tests {
         assert_eq!(container.profile, "default");
     }
 
+    #[test]
+    fn test_run_config_missing_profile() {
+        let mut config = Profile::default();
+        config.cmd.default = "zsh".to_string();
+
+        let info = ContainerInfo::new(
+            "test-uuid".to_string(),
+            "claudepod-test-deleted-profile",
+            "claudepod:test".to_string(),
+            config,
+        )
+        .unwrap();
+        assert!(Profile::load(&info.profile).is_err());
+
+        // The frozen config works without the profile, also after a round trip
+        let toml_str = toml::to_string_pretty(&info).unwrap();
+        let info: ContainerInfo = toml::from_str(&toml_str).unwrap();
+        let (command, args) = info.config.cmd.dispatch(&["--resume".to_string()]);
+        assert_eq!(command, "zsh");
+        assert_eq!(args, vec!["--resume".to_string()]);
+        assert_eq!(info.image_tag, "claudepod:test");
+    }
+
+    #[test]
+    fn test_drift() {
+        let info = test_info("test-uuid");
+        let mut profile = info.config.clone();
+        let drift = info.drift(&profile).unwrap();
+        assert!(drift.rebuild.is_empty() && drift.recreate.is_empty());
+
+        // A new port changes the profile hash, but not the image
+        profile
+            .docker
+            .ports
+            .push(PortMapping::parse("8080:3000").unwrap());
+        assert_ne!(profile.compute_hash().unwrap(), info.profile_hash);
+        let drift = info.drift(&profile).unwrap();
+        assert!(drift.rebuild.is_empty());
+        assert!(!drift.recreate.is_empty());
+        assert!(drift
+            .recreate
+            .iter()
+            .all(|field| field.path.starts_with("docker.ports")));
+
+        profile.container.user = "dev".to_string();
+        let drift = info.drift(&profile).unwrap();
+        assert_eq!(drift.rebuild.len(), 1);
+        assert_eq!(drift.rebuild[0].path, "container.user");
+    }
+
     #[test]
     fn test_container_not_found() {
         let data = ProjectData::new();
@@ -338,14 +505,7 @@ mod tests {
     fn test_remove_container() {
         let mut data = ProjectData::new();
 
-        let info = ContainerInfo {
-            uuid: "test-uuid".to_string(),
-            profile: "default".to_string(),
-            created_at: Utc::now(),
-            image_tag: "claudepod:test".to_string(),
-            docker: None,
-            commands: None,
-        };
+        let info = test_info("test-uuid");
 
         data.add_container("test", info);
         assert!(data.has_container("test"));
@@ -378,17 +538,7 @@ mod tests {
     #[test]
     fn test_serialization() {
         let mut data = ProjectData::new();
-        data.add_container(
-            "main",
-            ContainerInfo {
-                uuid: "test-uuid".to_string(),
-                profile: "default".to_string(),
-                created_at: Utc::now(),
-                image_tag: "claudepod:test".to_string(),
-                docker: None,
-                commands: None,
-            },
-        );
+        data.add_container("main", test_info("test-uuid"));
 
         let toml_str = toml::to_string_pretty(&data).unwrap();
         assert!(toml_str.contains("default = \"main\""));
@@ -440,4 +590,46 @@ mod tests {
         assert!(removed.is_some());
         assert!(index.get("abc123").is_none());
     }
+
+    #[test]
+    fn test_snapshots() {
+        let mut data = ProjectData::new();
+        let mut info = test_info("test-uuid-1234");
+        info.snapshots.push(Snapshot {
+            name: "before-refactor".to_string(),
+            created_at: Utc::now(),
+            description: String::new(),
+            image_tag: snapshot_tag("0123456789abcdef", "before-refactor"),
+            auto: false,
+        });
+        data.add_container("main", info);
+        data.add_container("gpu", test_info("test-uuid-5678"));
+
+        assert_eq!(
+            data.containers["main"]
+                .snapshot("before-refactor")
+                .unwrap()
+                .image_tag,
+            "claudepod:snap-0123456789abcdef-before-refactor"
+        );
+        assert_eq!(
+            data.snapshot_owner("before-refactor").map(String::as_str),
+            Some("main")
+        );
+        assert!(data.snapshot_owner("other").is_none());
+
+        // Containers without snapshots don't write the field
+        let toml = toml::to_string(&data.containers["gpu"]).unwrap();
+        assert!(!toml.contains("snapshots"));
+    }
+
+    #[test]
+    fn test_validate_snapshot_name() {
+        for name in ["before-refactor", "20250101-120000", "v1.2_rc"] {
+            assert!(validate_snapshot_name(name).is_ok(), "{}", name);
+        }
+        for name in ["", "-x", "Upper", "a/b", "a:b", &"x".repeat(65)] {
+            assert!(validate_snapshot_name(name).is_err(), "{}", name);
+        }
+    }
 }
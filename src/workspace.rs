@@ -0,0 +1,590 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::error::{ClaudepodError, Result};
+
+/// Git metadata is copied (so the agent can use git), but changes to it
+/// are never applied to the project
+const GIT_DIR: &str = ".git";
+
+/// Copy of the project that a container in overlay mode works on. Changes
+/// reach the project only through `apply`.
+pub struct Workspace {
+    root: PathBuf,
+}
+
+/// State of a file when the copy was last in sync with the project
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct FileState {
+    /// sha256 of the content (of the link target, for symlinks)
+    hash: String,
+    mode: u32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Base {
+    #[serde(default)]
+    files: BTreeMap<String, FileState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+}
+
+/// A pending change in the copy, by path relative to the project root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub path: String,
+    pub kind: ChangeKind,
+    /// Target of the symlink in the copy, if the path is one
+    pub link: Option<String>,
+}
+
+impl Change {
+    /// One-letter status, as in `git status --short`
+    pub fn status(&self) -> char {
+        match self.kind {
+            ChangeKind::Added => 'A',
+            ChangeKind::Modified => 'M',
+            ChangeKind::Deleted => 'D',
+        }
+    }
+
+    /// Whether the change is selected by `paths` (files or directories;
+    /// no paths select everything)
+    fn selected(&self, paths: &[String]) -> bool {
+        paths.is_empty()
+            || paths.iter().any(|p| {
+                let p = p.trim_end_matches('/');
+                self.path == p || self.path.starts_with(&format!("{}/", p))
+            })
+    }
+}
+
+/// Changes applied to the project, those skipped because the project file
+/// changed too, and those refused (with the reason) as unsafe to write
+#[derive(Debug, Default)]
+pub struct ApplyReport {
+    pub applied: Vec<Change>,
+    pub conflicts: Vec<Change>,
+    pub refused: Vec<(Change, String)>,
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o777
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> u32 {
+    0o644
+}
+
+/// State of a file or symlink, `None` if there is none at `path`
+fn file_state(path: &Path) -> Result<Option<FileState>> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut hasher = Sha256::new();
+    if metadata.file_type().is_symlink() {
+        hasher.update(b"symlink:");
+        hasher.update(fs::read_link(path)?.to_string_lossy().as_bytes());
+    } else if metadata.is_file() {
+        io::copy(&mut File::open(path)?, &mut hasher)?;
+    } else {
+        return Ok(None);
+    }
+
+    Ok(Some(FileState {
+        hash: format!("{:x}", hasher.finalize()),
+        mode: file_mode(&metadata),
+    }))
+}
+
+/// Copy a file or symlink, replacing whatever is at `to`
+fn copy_entry(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::symlink_metadata(to).is_ok_and(|m| m.is_dir()) {
+        fs::remove_dir_all(to)?;
+    } else if fs::symlink_metadata(to).is_ok() {
+        fs::remove_file(to)?;
+    }
+
+    let metadata = fs::symlink_metadata(from)?;
+    if metadata.file_type().is_symlink() {
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(fs::read_link(from)?, to)?;
+    } else {
+        fs::copy(from, to)?;
+        // Keep timestamps, so build tools don't rebuild everything
+        if let Ok(modified) = metadata.modified() {
+            File::options()
+                .write(true)
+                .open(to)?
+                .set_modified(modified)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove a file or symlink if present
+fn remove_entry(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e)
+            if e.kind() != io::ErrorKind::NotFound && e.kind() != io::ErrorKind::NotADirectory =>
+        {
+            Err(e.into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Parent directory of `path` under `root` that is a symlink, described
+/// with its target. Writing (or reading) through it would leave `root`.
+fn symlinked_parent(root: &Path, path: &str) -> Result<Option<String>> {
+    let parts: Vec<&str> = path.split('/').collect();
+    let mut current = root.to_path_buf();
+    for (i, part) in parts[..parts.len() - 1].iter().enumerate() {
+        current.push(part);
+        match fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Ok(Some(format!(
+                    "'{}' is a symlink to {}",
+                    parts[..=i].join("/"),
+                    fs::read_link(&current)?.display()
+                )));
+            }
+            Ok(_) => {}
+            // Missing parents are created as directories
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(None)
+}
+
+/// Files and symlinks under `root` (without .git), relative to it with '/'
+/// separators
+fn walk(root: &Path) -> Result<Vec<String>> {
+    let mut paths = Vec::new();
+    let entries = WalkDir::new(root)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| !(e.depth() == 1 && e.file_name() == GIT_DIR));
+    for entry in entries {
+        let entry =
+            entry.map_err(|e| ClaudepodError::Other(format!("Failed to read workspace: {}", e)))?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        let parts: Vec<_> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        paths.push(parts.join("/"));
+    }
+    Ok(paths)
+}
+
+impl Workspace {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Directory mounted at the project path in the container
+    pub fn tree(&self) -> PathBuf {
+        self.root.join("tree")
+    }
+
+    fn base_path(&self) -> PathBuf {
+        self.root.join("base.toml")
+    }
+
+    pub fn exists(&self) -> bool {
+        self.base_path().exists()
+    }
+
+    /// Copy the project, unless the copy already exists
+    pub fn ensure(&self, project_dir: &Path) -> Result<()> {
+        if self.exists() {
+            return Ok(());
+        }
+        println!("Copying the project into the overlay workspace...");
+        fs::create_dir_all(self.tree())?;
+        self.sync_all(project_dir)
+    }
+
+    /// Replace the copy's contents with the project's (in place, as a
+    /// container may have the tree mounted) and record them as the base
+    fn sync_all(&self, project_dir: &Path) -> Result<()> {
+        let tree = self.tree();
+        for entry in fs::read_dir(&tree)? {
+            let path = entry?.path();
+            if fs::symlink_metadata(&path)?.is_dir() {
+                fs::remove_dir_all(&path)?;
+            } else {
+                fs::remove_file(&path)?;
+            }
+        }
+
+        // The workspace lives in ~/.claudepod, which is inside a project at ~
+        let entries = WalkDir::new(project_dir)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|e| !e.path().starts_with(&self.root));
+        for entry in entries {
+            let entry = entry
+                .map_err(|e| ClaudepodError::Other(format!("Failed to copy the project: {}", e)))?;
+            let relative = entry
+                .path()
+                .strip_prefix(project_dir)
+                .unwrap_or(entry.path());
+            let target = tree.join(relative);
+            if entry.file_type().is_dir() {
+                fs::create_dir_all(&target)?;
+                fs::set_permissions(
+                    &target,
+                    entry.metadata().map_err(io::Error::from)?.permissions(),
+                )?;
+            } else {
+                copy_entry(entry.path(), &target)?;
+            }
+        }
+
+        let mut base = Base::default();
+        for path in walk(&tree)? {
+            if let Some(state) = file_state(&tree.join(&path))? {
+                base.files.insert(path, state);
+            }
+        }
+        self.save_base(&base)
+    }
+
+    fn load_base(&self) -> Result<Base> {
+        let content = fs::read_to_string(self.base_path()).map_err(|e| {
+            ClaudepodError::Other(format!(
+                "Overlay workspace not initialized ({}). It is created with the container.",
+                e
+            ))
+        })?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    fn save_base(&self, base: &Base) -> Result<()> {
+        let path = self.base_path();
+        let tmp = path.with_extension("toml.tmp");
+        fs::write(&tmp, toml::to_string(base)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Changes made in the copy since it was last in sync with the project
+    pub fn changes(&self) -> Result<Vec<Change>> {
+        let base = self.load_base()?;
+        let tree = self.tree();
+        let mut changes = Vec::new();
+        let mut seen = BTreeSet::new();
+
+        for path in walk(&tree)? {
+            let Some(state) = file_state(&tree.join(&path))? else {
+                continue;
+            };
+            let kind = match base.files.get(&path) {
+                None => Some(ChangeKind::Added),
+                Some(old) if *old != state => Some(ChangeKind::Modified),
+                Some(_) => None,
+            };
+            if let Some(kind) = kind {
+                let link = fs::read_link(tree.join(&path))
+                    .ok()
+                    .map(|target| target.to_string_lossy().into_owned());
+                changes.push(Change {
+                    path: path.clone(),
+                    kind,
+                    link,
+                });
+            }
+            seen.insert(path);
+        }
+
+        for path in base.files.keys().filter(|p| !seen.contains(*p)) {
+            changes.push(Change {
+                path: path.clone(),
+                kind: ChangeKind::Deleted,
+                link: None,
+            });
+        }
+
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(changes)
+    }
+
+    /// Apply changes (those under `paths`, or all) to the project. A change
+    /// whose project file changed since the copy was made is a conflict, and
+    /// is only applied with `force`. Changes that would write through a
+    /// symlink in the project, or replace a directory, are always refused.
+    pub fn apply(&self, project_dir: &Path, paths: &[String], force: bool) -> Result<ApplyReport> {
+        let mut base = self.load_base()?;
+        let tree = self.tree();
+        let mut report = ApplyReport::default();
+
+        for change in self.changes()?.into_iter().filter(|c| c.selected(paths)) {
+            let target = project_dir.join(&change.path);
+            let refused = match symlinked_parent(project_dir, &change.path)? {
+                Some(reason) => Some(reason),
+                // Replacing a directory would delete everything in it
+                None if fs::symlink_metadata(&target).is_ok_and(|m| m.is_dir()) => {
+                    Some(format!("'{}' is a directory in the project", change.path))
+                }
+                None => None,
+            };
+            if let Some(reason) = refused {
+                report.refused.push((change, reason));
+                continue;
+            }
+
+            if !force && file_state(&target)? != base.files.get(&change.path).cloned() {
+                report.conflicts.push(change);
+                continue;
+            }
+
+            match change.kind {
+                ChangeKind::Added | ChangeKind::Modified => {
+                    let source = tree.join(&change.path);
+                    copy_entry(&source, &target)?;
+                    if let Some(state) = file_state(&source)? {
+                        base.files.insert(change.path.clone(), state);
+                    }
+                }
+                ChangeKind::Deleted => {
+                    remove_entry(&target)?;
+                    base.files.remove(&change.path);
+                }
+            }
+            report.applied.push(change);
+        }
+
+        self.save_base(&base)?;
+        Ok(report)
+    }
+
+    /// Drop changes (those under `paths`, or all). Dropping all of them
+    /// refreshes the whole copy from the project. Returns the dropped changes.
+    pub fn discard(&self, project_dir: &Path, paths: &[String]) -> Result<Vec<Change>> {
+        let changes = self.changes()?;
+        if paths.is_empty() {
+            self.sync_all(project_dir)?;
+            return Ok(changes);
+        }
+
+        let mut base = self.load_base()?;
+        let tree = self.tree();
+        let mut discarded = Vec::new();
+        for change in changes.into_iter().filter(|c| c.selected(paths)) {
+            // Reading through a project symlink could expose files outside
+            // it; writing through a copy symlink could change them
+            let blocked = match symlinked_parent(project_dir, &change.path)? {
+                Some(reason) => Some(reason),
+                None => symlinked_parent(&tree, &change.path)?,
+            };
+            if let Some(reason) = blocked {
+                self.save_base(&base)?;
+                return Err(ClaudepodError::Other(format!(
+                    "Not discarding '{}': {}. Run 'claudepod discard' without paths \
+                     to refresh the whole copy.",
+                    change.path, reason
+                )));
+            }
+
+            let source = project_dir.join(&change.path);
+            let target = tree.join(&change.path);
+            match file_state(&source)? {
+                Some(state) => {
+                    copy_entry(&source, &target)?;
+                    base.files.insert(change.path.clone(), state);
+                }
+                None => {
+                    remove_entry(&target)?;
+                    base.files.remove(&change.path);
+                }
+            }
+            discarded.push(change);
+        }
+
+        self.save_base(&base)?;
+        Ok(discarded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A project with a few files, and its workspace
+    fn setup() -> (TempDir, PathBuf, Workspace) {
+        let dir = TempDir::new().unwrap();
+        let project = dir.path().join("project");
+        fs::create_dir_all(project.join("src")).unwrap();
+        fs::create_dir_all(project.join(GIT_DIR)).unwrap();
+        fs::write(project.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(project.join("README.md"), "# Project\n").unwrap();
+        fs::write(project.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+
+        let workspace = Workspace::new(dir.path().join("workspace"));
+        workspace.ensure(&project).unwrap();
+        (dir, project, workspace)
+    }
+
+    fn statuses(changes: &[Change]) -> Vec<String> {
+        changes
+            .iter()
+            .map(|c| format!("{} {}", c.status(), c.path))
+            .collect()
+    }
+
+    #[test]
+    fn test_changes_and_apply() {
+        let (_dir, project, workspace) = setup();
+        let tree = workspace.tree();
+        assert!(tree.join(".git/HEAD").exists());
+        assert!(workspace.changes().unwrap().is_empty());
+
+        fs::write(tree.join("src/main.rs"), "fn main() { run() }\n").unwrap();
+        fs::write(tree.join("src/lib.rs"), "pub fn run() {}\n").unwrap();
+        fs::remove_file(tree.join("README.md")).unwrap();
+        fs::write(tree.join(".git/HEAD"), "ref: refs/heads/agent\n").unwrap();
+
+        // The project is untouched until applied; .git is never a change
+        assert_eq!(
+            statuses(&workspace.changes().unwrap()),
+            ["D README.md", "A src/lib.rs", "M src/main.rs"]
+        );
+        assert_eq!(
+            fs::read_to_string(project.join("src/main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+
+        // Only the selected directory
+        let report = workspace
+            .apply(&project, &["src/".to_string()], false)
+            .unwrap();
+        assert_eq!(report.applied.len(), 2);
+        assert!(project.join("src/lib.rs").exists());
+        assert!(project.join("README.md").exists());
+        assert_eq!(statuses(&workspace.changes().unwrap()), ["D README.md"]);
+
+        let report = workspace.apply(&project, &[], false).unwrap();
+        assert_eq!(report.applied.len(), 1);
+        assert!(!project.join("README.md").exists());
+        assert!(workspace.changes().unwrap().is_empty());
+        assert_eq!(
+            fs::read_to_string(project.join(".git/HEAD")).unwrap(),
+            "ref: refs/heads/main\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_conflict() {
+        let (_dir, project, workspace) = setup();
+        fs::write(workspace.tree().join("README.md"), "# Agent\n").unwrap();
+        fs::write(project.join("README.md"), "# User\n").unwrap();
+
+        let report = workspace.apply(&project, &[], false).unwrap();
+        assert!(report.applied.is_empty());
+        assert_eq!(statuses(&report.conflicts), ["M README.md"]);
+        assert_eq!(
+            fs::read_to_string(project.join("README.md")).unwrap(),
+            "# User\n"
+        );
+
+        let report = workspace.apply(&project, &[], true).unwrap();
+        assert_eq!(report.applied.len(), 1);
+        assert_eq!(
+            fs::read_to_string(project.join("README.md")).unwrap(),
+            "# Agent\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_refuses_symlink_escape() {
+        let (dir, project, workspace) = setup();
+        let tree = workspace.tree();
+        let outside = dir.path().join("outside");
+        fs::create_dir(&outside).unwrap();
+
+        // A symlink out of the project is applied as a symlink...
+        std::os::unix::fs::symlink(&outside, tree.join("x")).unwrap();
+        let changes = workspace.changes().unwrap();
+        assert_eq!(changes[0].link, Some(outside.display().to_string()));
+        workspace.apply(&project, &[], false).unwrap();
+        assert!(fs::symlink_metadata(project.join("x"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+
+        // ...but nothing is ever written through it
+        fs::remove_file(tree.join("x")).unwrap();
+        fs::create_dir(tree.join("x")).unwrap();
+        fs::write(tree.join("x/authorized_keys"), "key\n").unwrap();
+        let report = workspace
+            .apply(&project, &["x/authorized_keys".to_string()], true)
+            .unwrap();
+        assert!(report.applied.is_empty());
+        assert_eq!(report.refused.len(), 1);
+        assert!(!outside.join("authorized_keys").exists());
+        assert!(workspace
+            .discard(&project, &["x/authorized_keys".to_string()])
+            .is_err());
+
+        // A directory in the project is not replaced by a file
+        fs::remove_dir_all(tree.join("src")).unwrap();
+        fs::write(tree.join("src"), "not a dir\n").unwrap();
+        let report = workspace
+            .apply(&project, &["src".to_string()], true)
+            .unwrap();
+        assert_eq!(statuses(&report.applied), ["D src/main.rs"]);
+        assert_eq!(report.refused.len(), 1);
+        assert!(project.join("src").is_dir());
+    }
+
+    #[test]
+    fn test_discard() {
+        let (_dir, project, workspace) = setup();
+        let tree = workspace.tree();
+        fs::write(tree.join("README.md"), "# Agent\n").unwrap();
+        fs::write(tree.join("notes.txt"), "todo\n").unwrap();
+
+        let discarded = workspace
+            .discard(&project, &["notes.txt".to_string()])
+            .unwrap();
+        assert_eq!(statuses(&discarded), ["A notes.txt"]);
+        assert!(!tree.join("notes.txt").exists());
+        assert_eq!(statuses(&workspace.changes().unwrap()), ["M README.md"]);
+
+        // Discarding everything also picks up changes made in the project
+        fs::write(project.join("src/main.rs"), "fn main() { new() }\n").unwrap();
+        workspace.discard(&project, &[]).unwrap();
+        assert!(workspace.changes().unwrap().is_empty());
+        assert_eq!(
+            fs::read_to_string(tree.join("src/main.rs")).unwrap(),
+            "fn main() { new() }\n"
+        );
+    }
+}